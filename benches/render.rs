@@ -0,0 +1,127 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use paperdoll::{BlendMode, Compositing, Paperdoll, PaperdollFactory, RenderOptions};
+
+const SIZE: u32 = 256;
+
+fn gradient(width: u32, height: u32, opaque: bool) -> Vec<u8> {
+    (0..width * height)
+        .flat_map(|i| {
+            let x = i % width;
+            let y = i / width;
+
+            let alpha = if opaque {
+                255
+            } else {
+                ((x + y) * 255 / (width + height)) as u8
+            };
+
+            [x as u8, y as u8, (x ^ y) as u8, alpha]
+        })
+        .collect()
+}
+
+fn setup(opaque: bool) -> (PaperdollFactory, Paperdoll) {
+    let mut factory = PaperdollFactory::default();
+
+    let doll_id = factory.dolls().next().map(|(id, _)| *id).unwrap();
+
+    let mut slot_map = vec![];
+
+    for i in 0..4 {
+        let fragment_id = factory.add_fragment().unwrap();
+
+        let fragment = factory.get_fragment_mut(fragment_id).unwrap();
+        fragment.image.width = SIZE / 2;
+        fragment.image.height = SIZE / 2;
+        fragment.image.pixels = gradient(SIZE / 2, SIZE / 2, opaque).into();
+
+        let slot_id = factory.add_slot().unwrap();
+
+        let slot = factory.get_slot_mut(slot_id).unwrap();
+        slot.positions[0].x = (i * SIZE / 4) as f32;
+        slot.positions[0].y = (i * SIZE / 4) as f32;
+        slot.candidates.push(fragment_id);
+
+        slot_map.push((slot_id, fragment_id));
+    }
+
+    let doll = factory.get_doll_mut(doll_id).unwrap();
+    doll.width = SIZE;
+    doll.height = SIZE;
+    doll.image.width = SIZE;
+    doll.image.height = SIZE;
+    doll.image.pixels = gradient(SIZE, SIZE, false).into();
+    doll.slots = slot_map.iter().map(|(slot_id, _)| *slot_id).collect();
+
+    let paperdoll = slot_map
+        .iter()
+        .fold(
+            factory.builder().doll(doll_id),
+            |builder, (slot_id, fragment_id)| builder.set_slot(*slot_id, *fragment_id),
+        )
+        .build();
+
+    (factory, paperdoll)
+}
+
+fn render(c: &mut Criterion) {
+    let (factory, paperdoll) = setup(false);
+
+    c.bench_function("render translucent", |b| {
+        b.iter(|| factory.render_paperdoll(black_box(&paperdoll)).unwrap())
+    });
+
+    let (factory, paperdoll) = setup(true);
+
+    c.bench_function("render opaque", |b| {
+        b.iter(|| factory.render_paperdoll(black_box(&paperdoll)).unwrap())
+    });
+}
+
+fn render_batch(c: &mut Criterion) {
+    let (factory, paperdoll) = setup(false);
+
+    let paperdolls = vec![paperdoll; 16];
+    let options = RenderOptions::default();
+
+    let mut images = vec![];
+
+    c.bench_function("render batch", |b| {
+        b.iter(|| factory.render_batch_into(black_box(&paperdolls), &options, &mut images))
+    });
+}
+
+/// Blends rows of pixels through the scalar and the chunked paths, to compare them on the same input.
+fn blend(c: &mut Criterion) {
+    for (name, opaque) in [("translucent", false), ("opaque", true)] {
+        let src = gradient(SIZE, SIZE, opaque);
+        let dst = gradient(SIZE, SIZE, false);
+
+        let mut buffer = dst.clone();
+
+        c.bench_function(&format!("blend scalar {}", name), |b| {
+            b.iter(|| {
+                buffer.copy_from_slice(&dst);
+
+                paperdoll::bench::blend_scalar(
+                    &mut buffer,
+                    black_box(&src),
+                    BlendMode::Normal,
+                    Compositing::Straight,
+                )
+            })
+        });
+
+        #[cfg(feature = "simd")]
+        c.bench_function(&format!("blend simd {}", name), |b| {
+            b.iter(|| {
+                buffer.copy_from_slice(&dst);
+
+                paperdoll::bench::blend_normal_chunked(&mut buffer, black_box(&src))
+            })
+        });
+    }
+}
+
+criterion_group!(benches, render, render_batch, blend);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, fmt};
+#[cfg(feature = "decode")]
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+#[cfg(feature = "decode")]
+use anyhow::bail;
+use anyhow::{anyhow, Result};
+
+#[cfg(feature = "decode")]
+use crate::decode::decode;
+use crate::image::ImageData;
+
+/// Identifies an image in the project.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Asset {
+    /// The background image of the doll with the given id.
+    Doll(u32),
+    /// The image of the fragment with the given id.
+    Fragment(u32),
+    /// The image of a layer of a fragment.
+    Layer {
+        /// The id of the fragment.
+        fragment: u32,
+        /// The index of the layer in the fragment.
+        index: usize,
+    },
+}
+
+impl fmt::Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Doll(id) => write!(f, "doll with id {}", id),
+            Self::Fragment(id) => write!(f, "fragment with id {}", id),
+            Self::Layer { fragment, index } => {
+                write!(f, "layer {} of fragment with id {}", index, fragment)
+            }
+        }
+    }
+}
+
+/// Resolves the paths of dolls, fragments and layers into images.
+///
+/// See [`PaperdollFactory::set_loader`](crate::PaperdollFactory::set_loader).
+pub trait AssetLoader: Send + Sync {
+    /// Returns the image at the given path.
+    ///
+    /// # Errors
+    ///
+    /// - Should return an error if the image can't be found or read.
+    fn load(&self, path: &str) -> Result<ImageData>;
+}
+
+/// Loads images from files, with paths relative to a base directory.
+///
+/// PNG, JPEG, GIF, BMP and WebP images are supported.
+///
+/// Absolute paths and paths containing `..` are rejected, so that a project can't read files outside the base directory.
+#[cfg(feature = "decode")]
+#[derive(Clone, Debug)]
+pub struct FsLoader {
+    base_dir: PathBuf,
+}
+
+#[cfg(feature = "decode")]
+impl FsLoader {
+    /// Creates a loader which resolves paths relative to the given directory.
+    pub fn new(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+        }
+    }
+}
+
+#[cfg(feature = "decode")]
+impl AssetLoader for FsLoader {
+    fn load(&self, path: &str) -> Result<ImageData> {
+        let is_relative = Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+        if !is_relative {
+            bail!("Path {} is outside the base directory", path);
+        }
+
+        let path = self.base_dir.join(path);
+
+        let bytes =
+            fs::read(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+
+        decode(&bytes).map_err(|e| anyhow!("Failed to decode {}: {}", path.display(), e))
+    }
+}
+
+/// Loads images from a map with paths as keys.
+///
+/// Useful for bundled assets and tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    images: HashMap<String, ImageData>,
+}
+
+impl MemoryLoader {
+    /// Creates an empty loader.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an image at the given path.
+    ///
+    /// Returns the image previously at that path, if any.
+    pub fn insert(&mut self, path: impl Into<String>, image: ImageData) -> Option<ImageData> {
+        self.images.insert(path.into(), image)
+    }
+
+    /// Removes the image at the given path.
+    pub fn remove(&mut self, path: &str) -> Option<ImageData> {
+        self.images.remove(path)
+    }
+}
+
+impl From<HashMap<String, ImageData>> for MemoryLoader {
+    fn from(images: HashMap<String, ImageData>) -> Self {
+        Self { images }
+    }
+}
+
+impl AssetLoader for MemoryLoader {
+    fn load(&self, path: &str) -> Result<ImageData> {
+        self.images
+            .get(path)
+            .cloned()
+            .ok_or(anyhow!("Failed to find image {}", path))
+    }
+}
@@ -0,0 +1,640 @@
+//! The blending core of the compositor.
+//!
+//! Blending in sRGB space is done with exact integer arithmetic: every channel is the correctly rounded result of the ideal formula.
+//! Premultiplied blending rounds the premultiplied colors to 8 bits before and after each blend, like an 8 bits render target does.
+//! Blending in linear light goes through `f32`, as the conversion between sRGB and linear light is not linear itself.
+//!
+//! All functions accept any pixel values and never panic.
+
+use std::sync::OnceLock;
+
+use crate::{blend_mode::BlendMode, render_options::Compositing};
+
+/// Blends the RGBA source pixels into the RGBA destination pixels.
+///
+/// Both buffers hold straight (non-premultiplied) alpha.
+/// Extra bytes of the longer buffer are ignored.
+pub(crate) fn blend(dst: &mut [u8], src: &[u8], mode: BlendMode, compositing: Compositing) {
+    #[cfg(feature = "simd")]
+    if mode == BlendMode::Normal && compositing == Compositing::Straight {
+        return blend_normal_chunked(dst, src);
+    }
+
+    blend_scalar(dst, src, mode, compositing);
+}
+
+/// Blends the RGBA source pixels into the RGBA destination pixels one by one, like [`blend`] without the chunked fast path.
+pub fn blend_scalar(dst: &mut [u8], src: &[u8], mode: BlendMode, compositing: Compositing) {
+    for (dst, src) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+        if src[3] == 0 {
+            continue;
+        }
+
+        if src[3] == 255 && mode == BlendMode::Normal {
+            dst.copy_from_slice(src);
+
+            continue;
+        }
+
+        match compositing {
+            Compositing::Straight => blend_pixel(dst, src, mode),
+            Compositing::Premultiplied => blend_pixel_premultiplied(dst, src, mode),
+            Compositing::Linear => blend_pixel_linear(dst, src, mode),
+        }
+    }
+}
+
+/// Blends a single pixel in sRGB space with exact integer arithmetic.
+///
+/// Follows the W3C compositing model: the color is mixed by the blend mode where both pixels overlap,
+/// then composited with source-over.
+fn blend_pixel(dst: &mut [u8], src: &[u8], mode: BlendMode) {
+    let sa = src[3] as u64;
+    let da = dst[3] as u64;
+
+    if mode == BlendMode::Erase {
+        dst[3] = div_round(da * (255 - sa), 255) as u8;
+
+        return;
+    }
+
+    // The alpha of the result, scaled by 255.
+    let alpha = sa * 255 + da * (255 - sa);
+
+    if alpha == 0 {
+        return;
+    }
+
+    for c in 0..3 {
+        let sc = src[c] as u64;
+        let dc = dst[c] as u64;
+
+        // The mixed color, scaled by 255.
+        let mixed = match mode {
+            BlendMode::Multiply => sc * dc,
+            BlendMode::Screen => (sc + dc) * 255 - sc * dc,
+            BlendMode::Overlay => {
+                if dc * 2 <= 255 {
+                    2 * sc * dc
+                } else {
+                    255 * 255 - 2 * (255 - sc) * (255 - dc)
+                }
+            }
+            BlendMode::Add => (sc + dc).min(255) * 255,
+            _ => sc * 255,
+        };
+
+        // The premultiplied color, scaled by 255 ^ 4.
+        let color = sa * (255 - da) * sc * 255 + sa * da * mixed + (255 - sa) * da * dc * 255;
+
+        dst[c] = div_round(color, alpha * 255).min(255) as u8;
+    }
+
+    dst[3] = div_round(alpha, 255) as u8;
+}
+
+/// Blends pixels with [`BlendMode::Normal`] in sRGB space, several pixels at a time.
+///
+/// Chunks whose source pixels are all transparent or all opaque are skipped or copied as a whole.
+/// The alpha checks of each chunk are written without branches, so that they can be vectorized by the compiler.
+/// Other chunks are blended pixel by pixel, with exactly the same results as [`blend_pixel`].
+#[cfg(feature = "simd")]
+pub fn blend_normal_chunked(dst: &mut [u8], src: &[u8]) {
+    const LANES: usize = 16;
+
+    // The remainders of both buffers only line up if they hold the same pixels.
+    let len = dst.len().min(src.len()) / 4 * 4;
+    let (dst, src) = (&mut dst[..len], &src[..len]);
+
+    let mut dst_chunks = dst.chunks_exact_mut(LANES * 4);
+    let mut src_chunks = src.chunks_exact(LANES * 4);
+
+    for (dst, src) in (&mut dst_chunks).zip(&mut src_chunks) {
+        let (any, all) = src.chunks_exact(4).fold((0, 255), |(any, all), pixel| {
+            (any | pixel[3], all & pixel[3])
+        });
+
+        if any == 0 {
+            continue;
+        }
+
+        if all == 255 {
+            dst.copy_from_slice(src);
+
+            continue;
+        }
+
+        for (dst, src) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            match src[3] {
+                0 => {}
+                255 => dst.copy_from_slice(src),
+                _ => blend_pixel_normal(dst, src),
+            }
+        }
+    }
+
+    for (dst, src) in dst_chunks
+        .into_remainder()
+        .chunks_exact_mut(4)
+        .zip(src_chunks.remainder().chunks_exact(4))
+    {
+        if src[3] != 0 {
+            blend_pixel_normal(dst, src);
+        }
+    }
+}
+
+/// Blends a single pixel with [`BlendMode::Normal`] in sRGB space.
+///
+/// Same as [`blend_pixel`] with the common factors removed, so that it fits in `u32`.
+#[cfg(feature = "simd")]
+fn blend_pixel_normal(dst: &mut [u8], src: &[u8]) {
+    let sa = src[3] as u32;
+    let da = dst[3] as u32;
+
+    // Weights of the source and the destination, scaled by 255 ^ 2.
+    let ws = sa * 255;
+    let wd = da * (255 - sa);
+
+    let alpha = ws + wd;
+
+    if alpha == 0 {
+        return;
+    }
+
+    for c in 0..3 {
+        let color = ws * src[c] as u32 + wd * dst[c] as u32;
+
+        dst[c] = ((color * 2 + alpha) / (alpha * 2)) as u8;
+    }
+
+    dst[3] = ((alpha * 2 + 255) / 510) as u8;
+}
+
+/// Blends a single pixel in sRGB space through 8 bits premultiplied colors.
+///
+/// Both pixels are premultiplied and rounded to 8 bits, blended with the same formula as [`blend_pixel`],
+/// and the premultiplied result is rounded to 8 bits before being stored as straight alpha.
+/// As converting 8 bits premultiplied colors to straight alpha and back is lossless,
+/// the stored pixel is exactly what an 8 bits premultiplied render target would hold.
+fn blend_pixel_premultiplied(dst: &mut [u8], src: &[u8], mode: BlendMode) {
+    let sa = src[3] as u64;
+    let da = dst[3] as u64;
+
+    let alpha = if mode == BlendMode::Erase {
+        div_round(da * (255 - sa), 255)
+    } else {
+        sa + div_round(da * (255 - sa), 255)
+    };
+
+    for c in 0..3 {
+        let sc = src[c] as u64;
+        let dc = dst[c] as u64;
+
+        let ps = div_round(sc * sa, 255);
+        let pd = div_round(dc * da, 255);
+
+        // The premultiplied mixed color, scaled by 255 ^ 2.
+        let mixed = match mode {
+            BlendMode::Multiply => ps * dc,
+            BlendMode::Screen => ps * 255 + sa * dc - ps * dc,
+            BlendMode::Overlay => {
+                if dc * 2 <= 255 {
+                    2 * ps * dc
+                } else {
+                    sa * 255 - 2 * (sa - ps) * (255 - dc)
+                }
+            }
+            BlendMode::Add => (ps * 255 + sa * dc).min(sa * 255),
+            _ => ps * 255,
+        };
+
+        // The premultiplied color rounded to 8 bits.
+        let color = match mode {
+            BlendMode::Erase => div_round(pd * (255 - sa), 255),
+            _ => div_round(
+                ps * (255 - da) * 255 + pd * (255 - sa) * 255 + da * mixed,
+                255 * 255,
+            ),
+        };
+
+        if alpha != 0 {
+            dst[c] = div_round(color * 255, alpha).min(255) as u8;
+        }
+    }
+
+    dst[3] = alpha as u8;
+}
+
+/// Blends a single pixel in linear light.
+fn blend_pixel_linear(dst: &mut [u8], src: &[u8], mode: BlendMode) {
+    let sa = src[3] as f32 / 255.0;
+    let da = dst[3] as f32 / 255.0;
+
+    if mode == BlendMode::Erase {
+        dst[3] = (da * (1.0 - sa) * 255.0).round() as u8;
+
+        return;
+    }
+
+    let alpha = sa + da * (1.0 - sa);
+
+    for c in 0..3 {
+        let sc = srgb_to_linear(src[c]);
+        let dc = srgb_to_linear(dst[c]);
+
+        let mixed = match mode {
+            BlendMode::Multiply => sc * dc,
+            BlendMode::Screen => sc + dc - sc * dc,
+            BlendMode::Overlay => {
+                if dc <= 0.5 {
+                    2.0 * sc * dc
+                } else {
+                    1.0 - 2.0 * (1.0 - sc) * (1.0 - dc)
+                }
+            }
+            BlendMode::Add => (sc + dc).min(1.0),
+            _ => sc,
+        };
+
+        let color = (sa * (1.0 - da) * sc + sa * da * mixed + (1.0 - sa) * da * dc) / alpha;
+
+        dst[c] = linear_to_srgb(color);
+    }
+
+    dst[3] = (alpha * 255.0).round().clamp(0.0, 255.0) as u8;
+}
+
+/// Divides and rounds half up. `d` must not be zero.
+fn div_round(n: u64, d: u64) -> u64 {
+    (n * 2 + d) / (d * 2)
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })[c as usize]
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Add,
+        BlendMode::Erase,
+    ];
+
+    const COMPOSITINGS: [Compositing; 3] = [
+        Compositing::Straight,
+        Compositing::Premultiplied,
+        Compositing::Linear,
+    ];
+
+    /// A xorshift generator, so that sampled colors are the same on every run.
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+
+            (self.0 >> 24) as u8
+        }
+
+        fn color(&mut self) -> [u8; 3] {
+            [self.next(), self.next(), self.next()]
+        }
+    }
+
+    /// The ideal result of [`blend_pixel`], in `f64` and scaled by 255.
+    fn reference(dst: [u8; 4], src: [u8; 4], mode: BlendMode) -> [f64; 4] {
+        let sa = src[3] as f64 / 255.0;
+        let da = dst[3] as f64 / 255.0;
+
+        let mut result = dst.map(|v| v as f64);
+
+        if mode == BlendMode::Erase {
+            result[3] = da * (1.0 - sa) * 255.0;
+
+            return result;
+        }
+
+        let alpha = sa + da * (1.0 - sa);
+
+        if alpha == 0.0 {
+            return result;
+        }
+
+        for c in 0..3 {
+            let sc = src[c] as f64 / 255.0;
+            let dc = dst[c] as f64 / 255.0;
+
+            let mixed = match mode {
+                BlendMode::Multiply => sc * dc,
+                BlendMode::Screen => sc + dc - sc * dc,
+                BlendMode::Overlay => {
+                    if dc <= 0.5 {
+                        2.0 * sc * dc
+                    } else {
+                        1.0 - 2.0 * (1.0 - sc) * (1.0 - dc)
+                    }
+                }
+                BlendMode::Add => (sc + dc).min(1.0),
+                _ => sc,
+            };
+
+            result[c] =
+                (sa * (1.0 - da) * sc + sa * da * mixed + (1.0 - sa) * da * dc) / alpha * 255.0;
+        }
+
+        result[3] = alpha * 255.0;
+
+        result
+    }
+
+    /// Colors at the extremes, and sampled ones.
+    fn colors(rng: &mut Rng) -> Vec<[u8; 3]> {
+        vec![
+            [0, 0, 0],
+            [255, 255, 255],
+            [0, 127, 128],
+            rng.color(),
+            rng.color(),
+        ]
+    }
+
+    #[test]
+    fn matches_reference_for_all_alphas() {
+        let mut rng = Rng(0x2545_f491);
+
+        for sa in 0..=255 {
+            for da in 0..=255 {
+                let src_colors = colors(&mut rng);
+                let dst_colors = colors(&mut rng);
+
+                for (sc, dc) in src_colors.iter().zip(dst_colors.iter().rev()) {
+                    let src = [sc[0], sc[1], sc[2], sa];
+                    let dst = [dc[0], dc[1], dc[2], da];
+
+                    for mode in MODES {
+                        let mut result = dst;
+
+                        blend(&mut result, &src, mode, Compositing::Straight);
+
+                        let expected = reference(dst, src, mode);
+
+                        for c in 0..4 {
+                            assert!(
+                                (result[c] as f64 - expected[c]).abs() <= 0.5 + 1e-9,
+                                "{:?} of {:?} over {:?} gives {:?}, expected {:?}",
+                                mode,
+                                src,
+                                dst,
+                                result,
+                                expected
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn never_panics_at_extremes() {
+        let pixels = (0..16u8)
+            .map(|bits| [0, 1, 2, 3].map(|i| if bits >> i & 1 == 1 { 255 } else { 0 }))
+            .collect::<Vec<_>>();
+
+        for mode in MODES {
+            for src in &pixels {
+                for dst in &pixels {
+                    for compositing in COMPOSITINGS {
+                        blend(&mut dst.clone(), src, mode, compositing);
+                    }
+
+                    blend_pixel(&mut dst.clone(), src, mode);
+                    blend_pixel_premultiplied(&mut dst.clone(), src, mode);
+                    blend_pixel_linear(&mut dst.clone(), src, mode);
+
+                    #[cfg(feature = "simd")]
+                    blend_pixel_normal(&mut dst.clone(), src);
+                }
+            }
+        }
+    }
+
+    /// Premultiplies a straight pixel and rounds it to 8 bits, like a premultiplied texture.
+    fn premultiply(pixel: [u8; 4]) -> [u8; 4] {
+        let alpha = pixel[3] as f64 / 255.0;
+
+        [
+            (pixel[0] as f64 * alpha).round() as u8,
+            (pixel[1] as f64 * alpha).round() as u8,
+            (pixel[2] as f64 * alpha).round() as u8,
+            pixel[3],
+        ]
+    }
+
+    /// Blends a premultiplied pixel into an 8 bits render target with `ONE, ONE_MINUS_SRC_ALPHA`, like a GPU.
+    fn gpu_blend(target: [u8; 4], src: [u8; 4]) -> [u8; 4] {
+        let sa = src[3] as f64 / 255.0;
+
+        std::array::from_fn(|c| {
+            let value = src[c] as f64 / 255.0 + target[c] as f64 / 255.0 * (1.0 - sa);
+
+            (value * 255.0).round() as u8
+        })
+    }
+
+    #[test]
+    fn premultiplied_matches_gpu_for_all_alphas() {
+        let mut rng = Rng(0x1b87_3593);
+
+        for sa in 0..=255 {
+            for da in 0..=255 {
+                for (sc, dc) in colors(&mut rng).iter().zip(colors(&mut rng).iter().rev()) {
+                    let src = [sc[0], sc[1], sc[2], sa];
+                    let dst = [dc[0], dc[1], dc[2], da];
+
+                    let mut result = dst;
+
+                    blend(
+                        &mut result,
+                        &src,
+                        BlendMode::Normal,
+                        Compositing::Premultiplied,
+                    );
+
+                    assert_eq!(
+                        premultiply(result),
+                        gpu_blend(premultiply(dst), premultiply(src)),
+                        "{:?} over {:?}",
+                        src,
+                        dst
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn premultiplied_matches_gpu_over_many_layers() {
+        let mut rng = Rng(0xcc9e_2d51);
+
+        for _ in 0..10000 {
+            let mut result = [0; 4];
+            let mut target = [0; 4];
+
+            for _ in 0..8 {
+                let [r, g, b] = rng.color();
+
+                let src = [r, g, b, rng.next()];
+
+                blend(
+                    &mut result,
+                    &src,
+                    BlendMode::Normal,
+                    Compositing::Premultiplied,
+                );
+
+                target = gpu_blend(target, premultiply(src));
+
+                assert_eq!(premultiply(result), target);
+            }
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn normal_pixel_matches_blend_pixel() {
+        let mut rng = Rng(0x9e37_79b9);
+
+        for sa in 0..=255 {
+            for da in 0..=255 {
+                for (sc, dc) in colors(&mut rng).iter().zip(colors(&mut rng).iter().rev()) {
+                    let src = [sc[0], sc[1], sc[2], sa];
+                    let dst = [dc[0], dc[1], dc[2], da];
+
+                    let mut expected = dst;
+                    blend_pixel(&mut expected, &src, BlendMode::Normal);
+
+                    let mut result = dst;
+                    blend_pixel_normal(&mut result, &src);
+
+                    assert_eq!(result, expected, "{:?} over {:?}", src, dst);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn chunked_matches_scalar() {
+        let mut rng = Rng(0x8526_4a1d);
+
+        // Transparent, opaque and mixed chunks of 16 pixels, followed by a remainder.
+        let alphas = (0..16 * 5 + 7)
+            .map(|i| match i / 16 {
+                0 => 0,
+                1 => 255,
+                2 => [0, 255][i % 2],
+                _ => rng.next(),
+            })
+            .collect::<Vec<_>>();
+
+        let src = alphas
+            .iter()
+            .flat_map(|alpha| {
+                let [r, g, b] = rng.color();
+
+                [r, g, b, *alpha]
+            })
+            .collect::<Vec<_>>();
+
+        let dst = (0..src.len()).map(|_| rng.next()).collect::<Vec<_>>();
+
+        let mut expected = dst.clone();
+        blend_scalar(
+            &mut expected,
+            &src,
+            BlendMode::Normal,
+            Compositing::Straight,
+        );
+
+        let mut result = dst;
+        blend_normal_chunked(&mut result, &src);
+
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn chunked_matches_scalar_on_random_inputs() {
+        let mut rng = Rng(0x68e3_1da4);
+
+        for _ in 0..2000 {
+            // Buffers of any length, with more or fewer bytes than the other one.
+            let src_len = rng.next() as usize * 2;
+            let dst_len = rng.next() as usize * 2;
+
+            let mut src = (0..src_len).map(|_| rng.next()).collect::<Vec<_>>();
+            let dst = (0..dst_len).map(|_| rng.next()).collect::<Vec<_>>();
+
+            // Runs of transparent and opaque pixels as well as mixed ones.
+            for chunk in src.chunks_mut(16 * 4) {
+                let kind = rng.next() % 4;
+
+                for pixel in chunk.chunks_exact_mut(4) {
+                    pixel[3] = match kind {
+                        0 => 0,
+                        1 => 255,
+                        2 => [0, 255][rng.next() as usize % 2],
+                        _ => pixel[3],
+                    };
+                }
+            }
+
+            let mut expected = dst.clone();
+            blend_scalar(
+                &mut expected,
+                &src,
+                BlendMode::Normal,
+                Compositing::Straight,
+            );
+
+            let mut result = dst;
+            blend_normal_chunked(&mut result, &src);
+
+            assert_eq!(result, expected, "{:?}", src);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// Ways to combine the pixels of a texture with the pixels beneath it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+pub enum BlendMode {
+    /// Draws the texture over the pixels beneath it.
+    #[default]
+    Normal,
+    /// Multiplies the colors. Darkens the result, useful for shadings.
+    Multiply,
+    /// Inverts, multiplies and inverts the colors again. Lightens the result, useful for highlights.
+    Screen,
+    /// Multiplies or screens the colors depending on the color beneath.
+    Overlay,
+    /// Adds the colors together.
+    Add,
+    /// Removes the pixels beneath by the alpha of the texture.
+    Erase,
+}
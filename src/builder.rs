@@ -1,20 +1,31 @@
 use std::collections::{BTreeMap, HashMap};
 
-use crate::{doll::Doll, fragment::Fragment, paperdoll::Paperdoll, slot::Slot};
+use crate::{
+    doll::Doll, fragment::Fragment, palette::Palette, paperdoll::Paperdoll, slot::Slot,
+    slot_style::SlotStyle, tint::Tint,
+};
+
+/// Palettes of builders created without any.
+static NO_PALETTES: BTreeMap<u32, Palette> = BTreeMap::new();
 
 /// A builder for construct [`Paperdoll`].
 pub struct PaperdollBuilder<'a> {
     doll: u32,
 
     slot_map: HashMap<u32, u32>,
+    styles: HashMap<u32, SlotStyle>,
+    palette: Option<u32>,
 
     dolls: &'a BTreeMap<u32, Doll>,
     slots: &'a BTreeMap<u32, Slot>,
     fragments: &'a BTreeMap<u32, Fragment>,
+    palettes: &'a BTreeMap<u32, Palette>,
 }
 
 impl<'a> PaperdollBuilder<'a> {
     /// Creates a new builder.
+    ///
+    /// No palette can be chosen unless some are given through [`Self::with_palettes`].
     pub fn new(
         dolls: &'a BTreeMap<u32, Doll>,
         slots: &'a BTreeMap<u32, Slot>,
@@ -23,12 +34,21 @@ impl<'a> PaperdollBuilder<'a> {
         Self {
             doll: u32::default(),
             slot_map: HashMap::new(),
+            styles: HashMap::new(),
+            palette: None,
             dolls,
             slots,
             fragments,
+            palettes: &NO_PALETTES,
         }
     }
 
+    /// Sets the palettes which can be chosen with [`Self::palette`] and [`Self::slot_palette`].
+    pub fn with_palettes(mut self, palettes: &'a BTreeMap<u32, Palette>) -> Self {
+        self.palettes = palettes;
+        self
+    }
+
     /// Sets the doll to be displayed.
     ///
     /// # Panics
@@ -43,6 +63,34 @@ impl<'a> PaperdollBuilder<'a> {
         self
     }
 
+    /// Sets the target palette for fragments in all slots.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the palette with the given id is not found.
+    pub fn palette(mut self, id: u32) -> Self {
+        if !self.palettes.contains_key(&id) {
+            panic!("Invalid key for palette: {}", id);
+        }
+
+        self.palette = Some(id);
+        self
+    }
+
+    /// Sets the opacity of the fragment used in the given slot.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the slot with the given id is not found.
+    pub fn opacity(mut self, slot_id: u32, opacity: f32) -> Self {
+        if !self.slots.contains_key(&slot_id) {
+            panic!("Invalid key for slot: {}", slot_id);
+        }
+
+        self.styles.entry(slot_id).or_default().opacity = Some(opacity);
+        self
+    }
+
     /// Sets the fragment to be used in the given slot.
     ///
     /// # Panics
@@ -61,11 +109,45 @@ impl<'a> PaperdollBuilder<'a> {
         self
     }
 
+    /// Sets the target palette for the fragment used in the given slot.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the slot or palette with the given id is not found.
+    pub fn slot_palette(mut self, slot_id: u32, palette_id: u32) -> Self {
+        if !self.slots.contains_key(&slot_id) {
+            panic!("Invalid key for slot: {}", slot_id);
+        }
+
+        if !self.palettes.contains_key(&palette_id) {
+            panic!("Invalid key for palette: {}", palette_id);
+        }
+
+        self.styles.entry(slot_id).or_default().palette = Some(palette_id);
+        self
+    }
+
+    /// Sets the tint of the fragment used in the given slot.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the slot with the given id is not found.
+    pub fn tint(mut self, slot_id: u32, tint: Tint) -> Self {
+        if !self.slots.contains_key(&slot_id) {
+            panic!("Invalid key for slot: {}", slot_id);
+        }
+
+        self.styles.entry(slot_id).or_default().tint = Some(tint);
+        self
+    }
+
     /// Constructs the `Paperdoll`.
     pub fn build(self) -> Paperdoll {
         Paperdoll {
             doll: self.doll,
             slot_map: self.slot_map,
+            styles: self.styles,
+            palette: self.palette,
         }
     }
 }
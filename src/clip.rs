@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// The source whose alpha restricts where a [slot](crate::Slot) can be drawn.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, Serialize)]
+pub enum Clip {
+    /// Clips to the background image of the doll.
+    ///
+    /// The doll must have an image, otherwise analysing and rendering fail.
+    Doll,
+    /// Clips to the fragments in the slot with the given id, which must be in the same doll.
+    ///
+    /// Nothing is drawn if no fragment is used in that slot.
+    Slot(u32),
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 ///
 /// The positive X-axis is rightward.
 /// The positive Y-axis is downward.
-#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Point {
     /// The distance from the Y-axis
     pub x: f32,
@@ -22,6 +22,14 @@ impl ops::Add<Self> for Point {
     }
 }
 
+impl ops::Mul<f32> for Point {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
 impl ops::Sub<Self> for Point {
     type Output = Self;
 
@@ -40,10 +48,134 @@ impl Point {
     }
 }
 
+/// A rectangle area used in `paperdoll`, in pixels.
+///
+/// Uses the same axes as [`Point`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    /// The left edge of the rectangle.
+    pub x: i32,
+    /// The top edge of the rectangle.
+    pub y: i32,
+    /// The width of the rectangle.
+    pub width: u32,
+    /// The height of the rectangle.
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the area covered by both rectangles.
+    /// The result is empty if they don't overlap.
+    pub fn intersect(&self, other: &Self) -> Self {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if left as i64 >= right || top as i64 >= bottom {
+            return Self::default();
+        }
+
+        // Never wider than either rectangle, so the size fits.
+        Self::new(
+            left,
+            top,
+            (right - left as i64) as u32,
+            (bottom - top as i64) as u32,
+        )
+    }
+
+    /// Does the rectangle cover no pixel?
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the smallest rectangle containing both rectangles.
+    /// Empty rectangles are ignored.
+    ///
+    /// The size is saturated if it doesn't fit in `u32`.
+    pub fn union(&self, other: &Self) -> Self {
+        if self.is_empty() {
+            return *other;
+        }
+
+        if other.is_empty() {
+            return *self;
+        }
+
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+
+        let size = |len: i64| len.min(u32::MAX as i64) as u32;
+
+        Self::new(
+            left,
+            top,
+            size(right - left as i64),
+            size(bottom - top as i64),
+        )
+    }
+
+    /// Returns the bottom edge, which may not fit in `i32`.
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    /// Returns the right edge, which may not fit in `i32`.
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+}
+
+pub(crate) fn is_default<T: Default + PartialEq>(t: &T) -> bool {
+    *t == T::default()
+}
+
 pub(crate) fn is_false(b: &bool) -> bool {
     !b
 }
 
+pub(crate) fn is_one(f: &f32) -> bool {
+    *f == 1.0
+}
+
 pub(crate) fn is_zero(u: &u32) -> bool {
     *u == 0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edges_beyond_i32_do_not_overflow() {
+        let rect = Rect::new(0, 0, 100, 100);
+
+        assert!(Rect::new(i32::MAX, 0, 10, 10).intersect(&rect).is_empty());
+        assert_eq!(
+            Rect::new(i32::MAX - 5, 0, 10, 10).intersect(&Rect::new(i32::MAX - 10, 0, 10, 10)),
+            Rect::new(i32::MAX - 5, 0, 5, 10)
+        );
+
+        assert_eq!(
+            Rect::new(-10, 20, u32::MAX, u32::MAX).intersect(&rect),
+            Rect::new(0, 20, 100, 80)
+        );
+
+        assert_eq!(
+            Rect::new(i32::MIN, 0, 1, 1).union(&Rect::new(i32::MAX, 0, 10, 10)),
+            Rect::new(i32::MIN, 0, u32::MAX, 10)
+        );
+    }
+}
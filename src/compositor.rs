@@ -0,0 +1,363 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    blend::blend,
+    blend_mode::BlendMode,
+    common::{Point, Rect},
+    image::{rgba_len, ImageData},
+    render_material::RenderPiece,
+    render_options::Compositing,
+    resample::{resample, sample, Filter},
+    transform::Affine,
+};
+
+/// A view of RGBA pixels to draw onto, which may be a part of a larger buffer.
+pub(crate) struct Canvas<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// The number of bytes between the starts of two rows.
+    pub stride: usize,
+    /// Starts at the top left pixel of the canvas.
+    pub pixels: &'a mut [u8],
+}
+
+impl<'a> Canvas<'a> {
+    /// Creates a canvas over the whole image.
+    pub fn from_image(image: &'a mut ImageData) -> Self {
+        Self {
+            width: image.width,
+            height: image.height,
+            stride: image.width as usize * 4,
+            pixels: Arc::make_mut(&mut image.pixels).as_mut_slice(),
+        }
+    }
+
+    /// Creates a transparent canvas of the given size over the buffer, which is cleared and resized as needed.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the buffer can't be allocated.
+    pub fn zeroed(width: u32, height: u32, buffer: &'a mut Vec<u8>) -> Result<Self> {
+        let len = rgba_len(width, height)?;
+
+        buffer.clear();
+        buffer.try_reserve_exact(len).map_err(|e| {
+            anyhow!(
+                "Failed to allocate an image of {}x{} pixels: {}",
+                width,
+                height,
+                e
+            )
+        })?;
+        buffer.resize(len, 0);
+
+        Ok(Self {
+            width,
+            height,
+            stride: width as usize * 4,
+            pixels: buffer,
+        })
+    }
+
+    /// Clears the canvas to transparent pixels.
+    pub fn clear(&mut self) {
+        let row_len = self.width as usize * 4;
+
+        for y in 0..self.height as usize {
+            self.pixels[y * self.stride..y * self.stride + row_len].fill(0);
+        }
+    }
+
+    fn row(&mut self, x: usize, y: usize, len: usize) -> &mut [u8] {
+        let cursor = y * self.stride + x * 4;
+
+        &mut self.pixels[cursor..cursor + len]
+    }
+}
+
+/// The alpha channel of pieces drawn onto an empty canvas, kept only for the area they cover.
+pub(crate) struct Mask {
+    /// The area of the canvas covered by the pieces.
+    rect: Rect,
+    alpha: Vec<u8>,
+}
+
+impl Mask {
+    /// Returns the alpha of a row of pixels of the canvas, which must lie inside the mask.
+    fn row(&self, x: i32, y: i32, len: usize) -> &[u8] {
+        let cursor =
+            (y - self.rect.y) as usize * self.rect.width as usize + (x - self.rect.x) as usize;
+
+        &self.alpha[cursor..cursor + len]
+    }
+}
+
+/// Returns the alpha channel of the given pieces drawn onto an empty canvas of the given size.
+///
+/// The pieces are drawn into `scratch`, which can be reused by later calls.
+///
+/// # Errors
+///
+/// - Will return an error if the mask can't be allocated.
+pub(crate) fn alpha_mask(
+    width: u32,
+    height: u32,
+    pieces: &[&RenderPiece],
+    compositing: Compositing,
+    scratch: &mut Vec<u8>,
+) -> Result<Mask> {
+    let rect = pieces
+        .iter()
+        .fold(Rect::default(), |rect, piece| rect.union(&piece.bounds()))
+        .intersect(&Rect::new(0, 0, width, height));
+
+    let mut canvas = Canvas::zeroed(rect.width, rect.height, scratch)?;
+
+    for piece in pieces {
+        draw(
+            &mut canvas,
+            &piece.shifted(rect),
+            BlendMode::Normal,
+            compositing,
+        );
+    }
+
+    let mut alpha = vec![];
+
+    alpha.try_reserve_exact(scratch.len() / 4).map_err(|e| {
+        anyhow!(
+            "Failed to allocate a mask of {}x{} pixels: {}",
+            rect.width,
+            rect.height,
+            e
+        )
+    })?;
+    alpha.extend(scratch.chunks_exact(4).map(|pixel| pixel[3]));
+
+    Ok(Mask { rect, alpha })
+}
+
+/// Draws the piece onto the canvas, blending it over existing pixels.
+pub(crate) fn draw_piece(dst: &mut Canvas, piece: &RenderPiece, compositing: Compositing) {
+    draw(dst, piece, piece.blend_mode, compositing);
+}
+
+/// Draws the piece onto the canvas like [`draw_piece`], but only where the mask is not transparent.
+///
+/// The mask must come from a canvas with the same size.
+/// The piece is drawn into `scratch` first, which can be reused by later calls.
+///
+/// # Errors
+///
+/// - Will return an error if `scratch` can't be allocated.
+pub(crate) fn draw_piece_clipped(
+    dst: &mut Canvas,
+    piece: &RenderPiece,
+    mask: &Mask,
+    compositing: Compositing,
+    scratch: &mut Vec<u8>,
+) -> Result<()> {
+    let area = piece.bounds().intersect(&mask.rect);
+
+    if area.is_empty() {
+        return Ok(());
+    }
+
+    let mut layer = Canvas::zeroed(area.width, area.height, scratch)?;
+
+    draw(
+        &mut layer,
+        &piece.shifted(area),
+        BlendMode::Normal,
+        compositing,
+    );
+
+    let row_len = area.width as usize * 4;
+
+    for (y, row) in scratch.chunks_exact_mut(row_len).enumerate() {
+        let y = area.y + y as i32;
+
+        for (pixel, mask) in row
+            .chunks_exact_mut(4)
+            .zip(mask.row(area.x, y, area.width as usize))
+        {
+            pixel[3] = ((pixel[3] as u32 * *mask as u32 + 127) / 255) as u8;
+        }
+
+        blend(
+            dst.row(area.x as usize, y as usize, row_len),
+            row,
+            piece.blend_mode,
+            compositing,
+        );
+    }
+
+    Ok(())
+}
+
+fn draw(dst: &mut Canvas, piece: &RenderPiece, mode: BlendMode, compositing: Compositing) {
+    if piece.image.is_empty() {
+        return;
+    }
+
+    // Shares the pixels with the piece until they need to be modified.
+    let mut image = piece.image.clone();
+
+    if let Some(tint) = &piece.tint {
+        tint.apply(Arc::make_mut(&mut image.pixels).as_mut_slice());
+    }
+
+    if piece.opacity < 1.0 {
+        let opacity = piece.opacity.max(0.0);
+
+        for pixel in Arc::make_mut(&mut image.pixels).chunks_exact_mut(4) {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+    }
+
+    let matrix = Affine(piece.matrix());
+
+    let [a, _, _, d, e, f] = matrix.0;
+
+    let (scale_x, scale_y) = (a.abs(), d.abs());
+
+    // Upscaling the whole image is only worth it if the result is not larger than the canvas.
+    // Otherwise only the pixels covering the canvas are sampled.
+    let upscaled = scale_x > 1.0 || scale_y > 1.0;
+    let upscaled_area = image.width as f32 * scale_x * image.height as f32 * scale_y;
+
+    if piece.is_aligned() && (!upscaled || upscaled_area <= dst.width as f32 * dst.height as f32) {
+        if a < 0.0 || d < 0.0 {
+            image = image.flipped(a < 0.0, d < 0.0);
+        }
+
+        let (width, height) = (image.width as f32, image.height as f32);
+
+        if upscaled {
+            image = resample(
+                &image,
+                (width * scale_x) as u32,
+                (height * scale_y) as u32,
+                Filter::Nearest,
+            );
+        }
+
+        copy_pixels(
+            dst,
+            &image,
+            e.min(e + a * width) as isize,
+            f.min(f + d * height) as isize,
+            mode,
+            compositing,
+        );
+    } else {
+        draw_transformed(dst, &image, &matrix, piece.filter, mode, compositing);
+    }
+}
+
+fn copy_pixels(
+    dst: &mut Canvas,
+    src: &ImageData,
+    dx: isize,
+    dy: isize,
+    mode: BlendMode,
+    compositing: Compositing,
+) {
+    if src.is_empty() {
+        return;
+    }
+
+    if dx >= dst.width as isize
+        || (dx + src.width as isize) < 0
+        || dy >= dst.height as isize
+        || (dy + src.height as isize) < 0
+    {
+        return;
+    }
+
+    let src_row_len = src.width as usize * 4;
+
+    let sx = if dx >= 0 { 0 } else { dx.abs_diff(0) };
+    let sy = if dy >= 0 { 0 } else { dy.abs_diff(0) };
+
+    let dx = 0.max(dx) as usize;
+    let dy = 0.max(dy) as usize;
+
+    let copy_width = (src.width as usize - sx).min(dst.width as usize - dx) * 4;
+
+    let rows = (src.height as usize - sy).min(dst.height as usize - dy);
+
+    for row in 0..rows {
+        let src_cursor = (sy + row) * src_row_len + sx * 4;
+
+        if src_cursor + copy_width > src.pixels.len() {
+            break;
+        }
+
+        blend(
+            dst.row(dx, dy + row, copy_width),
+            &src.pixels[src_cursor..src_cursor + copy_width],
+            mode,
+            compositing,
+        );
+    }
+}
+
+/// Draws the image onto the canvas through the given matrix, which maps coordinates of the image to those of the canvas.
+fn draw_transformed(
+    dst: &mut Canvas,
+    src: &ImageData,
+    matrix: &Affine,
+    filter: Filter,
+    mode: BlendMode,
+    compositing: Compositing,
+) {
+    if !src.has_pixels() {
+        return;
+    }
+
+    let Some(inverse) = matrix.inverse() else {
+        return;
+    };
+
+    let corners = [
+        Point::new(0.0, 0.0),
+        Point::new(src.width as f32, 0.0),
+        Point::new(0.0, src.height as f32),
+        Point::new(src.width as f32, src.height as f32),
+    ]
+    .map(|corner| matrix.apply(corner));
+
+    let (min_x, min_y, max_x, max_y) = corners.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(min_x, min_y, max_x, max_y), corner| {
+            (
+                min_x.min(corner.x),
+                min_y.min(corner.y),
+                max_x.max(corner.x),
+                max_y.max(corner.y),
+            )
+        },
+    );
+
+    let left = min_x.floor().max(0.0) as usize;
+    let top = min_y.floor().max(0.0) as usize;
+    let right = (max_x.ceil().max(0.0) as usize).min(dst.width as usize);
+    let bottom = (max_y.ceil().max(0.0) as usize).min(dst.height as usize);
+
+    // How far one pixel of the canvas reaches along each axis of the image.
+    let [ia, ib, ic, id, _, _] = inverse.0;
+    let footprint = (ia.hypot(ic), ib.hypot(id));
+
+    for y in top..bottom {
+        for x in left..right {
+            let point = inverse.apply(Point::new(x as f32 + 0.5, y as f32 + 0.5));
+
+            if let Some(pixel) = sample(src, point.x, point.y, filter, footprint) {
+                blend(dst.row(x, y, 4), &pixel, mode, compositing);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use image::DynamicImage;
+
+use crate::image::{ColorType, ImageData};
+
+/// Decodes an encoded image, eg. the content of a PNG file.
+///
+/// The format is guessed from the content. PNG, JPEG, GIF, BMP and WebP are supported.
+/// The color type of the result is the closest one to that of the encoded image.
+pub(crate) fn decode(bytes: &[u8]) -> Result<ImageData> {
+    let image = image::load_from_memory(bytes)?;
+
+    let width = image.width();
+    let height = image.height();
+
+    let (color_type, pixels) = match image {
+        DynamicImage::ImageLuma8(image) => (ColorType::L8, image.into_raw()),
+        DynamicImage::ImageLumaA8(image) => (ColorType::La8, image.into_raw()),
+        DynamicImage::ImageRgb8(image) => (ColorType::Rgb8, image.into_raw()),
+        DynamicImage::ImageRgba8(image) => (ColorType::Rgba, image.into_raw()),
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => (
+            ColorType::Rgba32F,
+            image
+                .into_rgba32f()
+                .into_raw()
+                .into_iter()
+                .flat_map(f32::to_ne_bytes)
+                .collect(),
+        ),
+        // 16 bits images, and formats added later.
+        image => (
+            ColorType::Rgba16,
+            image
+                .into_rgba16()
+                .into_raw()
+                .into_iter()
+                .flat_map(u16::to_ne_bytes)
+                .collect(),
+        ),
+    };
+
+    Ok(ImageData {
+        width,
+        height,
+        color_type,
+        pixels: Arc::new(pixels),
+    })
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::{is_zero, Point},
+    common::{is_default, is_zero, Point},
     image::ImageData,
 };
 
@@ -26,6 +26,13 @@ pub struct Doll {
     #[serde(default, skip_serializing_if = "Point::is_zero")]
     pub offset: Point,
 
+    /// The draw order of the background image.
+    /// The background image is drawn before all slots with a depth greater than or equal to it.
+    ///
+    /// See [`Slot::depth`](crate::Slot::depth).
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub depth: i32,
+
     /// A list of id of [slots](crate::Slot) those can be used in the doll.
     pub slots: Vec<u32>,
 
@@ -47,6 +54,7 @@ impl Doll {
             width: 0,
             height: 0,
             offset: Point::default(),
+            depth: 0,
             slots: vec![],
             path: String::default(),
             image: ImageData::default(),
@@ -0,0 +1,39 @@
+use std::io::Write;
+
+use anyhow::Result;
+use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder};
+
+use crate::image::{ColorType, ImageData};
+
+/// Encodes the image as PNG into the given writer.
+///
+/// Images of [`ColorType::Rgba32F`] are stored with 16 bits channels, as PNG doesn't support floats.
+/// Images of other color types are stored without loss.
+pub(crate) fn encode_png(image: &ImageData, writer: impl Write) -> Result<()> {
+    image.validate()?;
+
+    let converted;
+
+    let (pixels, color_type) = match image.color_type {
+        ColorType::Rgba => (image.pixels.as_slice(), ExtendedColorType::Rgba8),
+        ColorType::Rgb8 => (image.pixels.as_slice(), ExtendedColorType::Rgb8),
+        ColorType::L8 => (image.pixels.as_slice(), ExtendedColorType::L8),
+        ColorType::La8 => (image.pixels.as_slice(), ExtendedColorType::La8),
+        ColorType::Rgba16 => (image.pixels.as_slice(), ExtendedColorType::Rgba16),
+        ColorType::Rgba32F => {
+            converted = image
+                .pixels
+                .chunks_exact(4)
+                .map(|v| f32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+                // NaN becomes 0.
+                .flat_map(|v| ((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_ne_bytes())
+                .collect::<Vec<_>>();
+
+            (converted.as_slice(), ExtendedColorType::Rgba16)
+        }
+    };
+
+    PngEncoder::new(writer).write_image(pixels, image.width, image.height, color_type)?;
+
+    Ok(())
+}
@@ -1,18 +1,44 @@
-use std::collections::{btree_map::Iter, BTreeMap, HashMap};
+#[cfg(feature = "ppd")]
+use std::io::Read;
+#[cfg(any(feature = "decode", feature = "encode"))]
+use std::path::Path;
+use std::{
+    collections::{btree_map::Iter, BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+};
+#[cfg(feature = "encode")]
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
 
 use anyhow::{anyhow, bail, Result};
 
+#[cfg(feature = "decode")]
+use crate::asset::FsLoader;
+#[cfg(feature = "ppd")]
+use crate::ppd;
 use crate::{
+    asset::{Asset, AssetLoader},
+    blend_mode::BlendMode,
     builder::PaperdollBuilder,
+    clip::Clip,
+    common::Rect,
+    compositor::{alpha_mask, draw_piece, draw_piece_clipped, Canvas, Mask},
     doll::Doll,
     fragment::Fragment,
     id_factory::IdFactory,
-    image::{ColorType, ImageData},
+    image::{rgba_len, ColorType, ImageData},
     manifest::Manifest,
     meta::Meta,
+    palette::Palette,
     paperdoll::Paperdoll,
+    render_cache::RenderCache,
     render_material::{RenderMaterial, RenderPiece},
+    render_options::{Framing, RenderOptions},
+    resample::{resample, Filter},
     slot::Slot,
+    transform::Transform,
 };
 
 /// A factory helps you manage the `paperdoll` project.
@@ -33,7 +59,7 @@ use crate::{
 /// let fragment = factory.get_fragment_mut(fragment_id).unwrap();
 /// fragment.image.width = 1;
 /// fragment.image.height = 1;
-/// fragment.image.pixels = vec![0, 0, 0, 255];
+/// fragment.image.pixels = vec![0, 0, 0, 255].into();
 ///
 /// // Creates a new slot.
 /// let slot_id = factory.add_slot().unwrap();
@@ -67,10 +93,18 @@ pub struct PaperdollFactory {
     doll_id_factory: IdFactory,
     slot_id_factory: IdFactory,
     fragment_id_factory: IdFactory,
+    palette_id_factory: IdFactory,
 
     dolls: BTreeMap<u32, Doll>,
     slots: BTreeMap<u32, Slot>,
     fragments: BTreeMap<u32, Fragment>,
+    palettes: BTreeMap<u32, Palette>,
+
+    cache: Mutex<RenderCache>,
+
+    loader: Option<Box<dyn AssetLoader>>,
+    /// Images resolved by the loader, with paths as keys.
+    loaded: Mutex<HashMap<String, ImageData>>,
 }
 
 impl Default for PaperdollFactory {
@@ -102,7 +136,7 @@ impl PaperdollFactory {
             dolls.insert(doll.id(), doll);
         }
 
-        if dolls.len() == 0 {
+        if dolls.is_empty() {
             let doll = Doll::new(doll_id_factory.get_next()?);
 
             dolls.insert(doll.id(), doll);
@@ -136,23 +170,58 @@ impl PaperdollFactory {
             doll_id_factory,
             slot_id_factory,
             fragment_id_factory,
+            palette_id_factory: IdFactory::new(),
 
             dolls,
             slots,
             fragments,
+            palettes: BTreeMap::new(),
+
+            cache: Mutex::default(),
+
+            loader: None,
+            loaded: Mutex::default(),
         })
     }
 
     /// Creates a paper doll factory from the given manifest.
     ///
-    /// Calls [`Self::new`] under the hood.
+    /// Calls [`Self::new`] under the hood, then adds the palettes of the manifest.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if there are duplicated ids for dolls, slots, fragments, or palettes.
     pub fn from_manifest(manifest: Manifest) -> Result<Self> {
-        Self::new(
+        let mut factory = Self::new(
             manifest.meta,
             manifest.dolls,
             manifest.slots,
             manifest.fragments,
-        )
+        )?;
+
+        for palette in manifest.palettes {
+            factory
+                .palette_id_factory
+                .take_up(palette.id())
+                .map_err(|e| anyhow!("Add palette with id {} failed: {}", palette.id(), e))?;
+
+            factory.palettes.insert(palette.id(), palette);
+        }
+
+        Ok(factory)
+    }
+
+    /// Creates a paper doll factory from a `ppd` archive, with all images loaded.
+    ///
+    /// See [`Self::write_ppd`] for the format of the archive.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the archive can't be read, or the manifest is invalid.
+    /// - Will return an error if an image is missing in the archive or can't be decoded.
+    #[cfg(feature = "ppd")]
+    pub fn from_ppd(reader: impl Read) -> Result<Self> {
+        ppd::read(reader)
     }
 
     /// Adds a new doll to the factory.
@@ -197,6 +266,27 @@ impl PaperdollFactory {
         Ok(id)
     }
 
+    /// Adds a new palette to the factory.
+    ///
+    /// Returns the id of the palette.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the id pool is full.
+    pub fn add_palette(&mut self) -> Result<u32> {
+        let palette = Palette::new(
+            self.palette_id_factory
+                .get_next()
+                .map_err(|e| anyhow!("Add new palette failed: {}", e))?,
+        );
+
+        let id = palette.id();
+
+        self.palettes.insert(id, palette);
+
+        Ok(id)
+    }
+
     /// Adds a new slot to the factory.
     ///
     /// Returns the id of the slot.
@@ -221,28 +311,79 @@ impl PaperdollFactory {
     /// Returns the structure of the paper doll.
     /// Can be used later for drawing the paper doll as a replacement for [`Self::render`] if you want to handle the rendering process yourself.
     ///
+    /// Empty images of the doll and the used fragments are loaded from their paths if a [loader](Self::set_loader) is set.
+    ///
     /// # Arguments
     ///
     /// - `doll`: The id of the doll to be displayed.
     /// - `slot_map`: A map with the id of slot as key and the id of fragment which is used in this slot as value.
-    /// - `only_id`: Whether the result `RenderMaterial` needs to contain the pixel data of the images?
-    /// If `true`, the pixel data will be cloned.
-    /// It's recommended to set this to `false` if you do not rely on pixels returning here for rendering, eg. you have stored the pixel data elsewhere.
+    /// - `only_id`: Whether the result `RenderMaterial` should leave out the pixel data of the images?
+    ///   If `false`, the pixel data is shared with the images stored in this factory, without being copied.
+    ///   Images of other [color types](crate::ColorType) are converted to [`ColorType::Rgba`](crate::ColorType::Rgba),
+    ///   and [palette swaps](RenderPiece::palette_swap) are applied.
+    ///   It's recommended to set this to `true` if you do not rely on pixels returning here for rendering, eg. you have stored the pixel data elsewhere.
     pub fn analyse(
         &self,
         doll: u32,
         slot_map: &HashMap<u32, u32>,
         only_id: bool,
     ) -> Result<RenderMaterial> {
+        let paperdoll = Paperdoll {
+            doll,
+            slot_map: slot_map.clone(),
+            ..Default::default()
+        };
+
+        self.analyse_paperdoll(&paperdoll, only_id)
+    }
+
+    /// Returns the structure of the given paperdoll.
+    ///
+    /// Works like [`Self::analyse`], with the [styles](crate::SlotStyle) and the [palette](crate::Palette) of the paperdoll applied.
+    pub fn analyse_paperdoll(
+        &self,
+        paperdoll: &Paperdoll,
+        only_id: bool,
+    ) -> Result<RenderMaterial> {
+        self.analyse_scaled(paperdoll, only_id, 1.0)
+    }
+
+    /// Returns the structure of the given paperdoll like [`Self::analyse_paperdoll`], scaled by the given factor.
+    ///
+    /// Unless the factor is an integer, fragments in constrainted slots are resampled to the scaled size of the slot at once,
+    /// instead of being resampled to the size of the slot and filtered again while compositing.
+    fn analyse_scaled(
+        &self,
+        paperdoll: &Paperdoll,
+        only_id: bool,
+        scale: f32,
+    ) -> Result<RenderMaterial> {
+        let doll = paperdoll.doll;
+        let slot_map = &paperdoll.slot_map;
+
         let doll = self
             .get_doll(doll)
             .ok_or(anyhow!("Failed to find doll with id {}", doll))?;
 
-        let width = doll.width;
-        let height = doll.height;
+        let scaled = |len: u32| (len as f64 * scale as f64).round();
+
+        if scaled(doll.width) > u32::MAX as f64 || scaled(doll.height) > u32::MAX as f64 {
+            bail!(
+                "Scale factor {} is too large for a doll of {}x{} pixels",
+                scale,
+                doll.width,
+                doll.height
+            );
+        }
+
+        let width = scaled(doll.width) as u32;
+        let height = scaled(doll.height) as u32;
 
         let mut slots = vec![];
 
+        // A slot clipped to the doll, which needs the doll to have an image.
+        let mut clipped_to_doll = None;
+
         for slot_id in &doll.slots {
             let slot = self
                 .get_slot(*slot_id)
@@ -252,66 +393,282 @@ impl PaperdollFactory {
                 .get(slot_id)
                 .or_else(|| slot.required.then(|| slot.candidates.first()).flatten());
 
+            if slot.clip == Some(Clip::Doll) {
+                clipped_to_doll.get_or_insert(*slot_id);
+            }
+
+            if let Some(Clip::Slot(id)) = slot.clip {
+                if !doll.slots.contains(&id) {
+                    bail!(
+                        "Slot with id {} is clipped to slot with id {}, which is not in doll with id {}",
+                        slot_id,
+                        id,
+                        doll.id()
+                    );
+                }
+            }
+
             if let Some(fragment_id) = fragment_id {
+                let style = paperdoll.styles.get(slot_id);
+
                 let fragment = self
                     .get_fragment(*fragment_id)
                     .ok_or(anyhow!("Failed to find fragment with id {}", fragment_id))?;
 
-                if fragment.image.is_empty() {
+                let fragment_image =
+                    self.resolve(&fragment.image, &fragment.path).map_err(|e| {
+                        anyhow!(
+                            "Failed to load image of fragment with id {}: {}",
+                            fragment_id,
+                            e
+                        )
+                    })?;
+
+                let layer_images = fragment
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, layer)| {
+                        self.resolve(&layer.image, &layer.path).map_err(|e| {
+                            anyhow!(
+                                "Failed to load image of layer {} of fragment with id {}: {}",
+                                index,
+                                fragment_id,
+                                e
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                if fragment_image.is_empty() && layer_images.is_empty() {
                     bail!(
                         "Fragment with id {} is used but it contains no image data",
                         fragment_id
                     );
                 }
 
-                for position in &slot.positions {
-                    let mut image = ImageData {
-                        width: fragment.image.width,
-                        height: fragment.image.height,
-                        color_type: fragment.image.color_type,
-                        ..Default::default()
+                if let Some(index) = layer_images.iter().position(|image| image.is_empty()) {
+                    bail!(
+                        "Layer {} of fragment with id {} is used but it contains no image data",
+                        index,
+                        fragment_id
+                    );
+                }
+
+                let palette_swap = fragment
+                    .palette
+                    .zip(style.and_then(|style| style.palette).or(paperdoll.palette))
+                    .filter(|(from, to)| from != to);
+
+                if let Some((from, to)) = palette_swap {
+                    for id in [from, to] {
+                        if !self.palettes.contains_key(&id) {
+                            bail!("Failed to find palette with id {}", id);
+                        }
+                    }
+                }
+
+                let layers = (!fragment_image.is_empty())
+                    .then_some((None, &fragment_image, fragment.pivot, 0))
+                    .into_iter()
+                    .chain(fragment.layers.iter().zip(&layer_images).enumerate().map(
+                        |(index, (layer, image))| (Some(index), image, layer.pivot, layer.depth),
+                    ));
+
+                for (layer, source, pivot, layer_depth) in layers {
+                    let mut source = if only_id {
+                        source.validate().map(|_| source.clone())
+                    } else {
+                        source.to_rgba()
+                    }
+                    .map_err(|e| match layer {
+                        Some(index) => anyhow!(
+                            "Layer {} of fragment with id {} contains invalid image data: {}",
+                            index,
+                            fragment_id,
+                            e
+                        ),
+                        None => anyhow!(
+                            "Fragment with id {} contains invalid image data: {}",
+                            fragment_id,
+                            e
+                        ),
+                    })?;
+
+                    // Swaps colors before resampling, as filtering mixes colors which would no longer match the palette.
+                    if let Some((from, to)) = palette_swap.filter(|_| !only_id) {
+                        self.palettes[&from].remap(
+                            &self.palettes[&to],
+                            Arc::make_mut(&mut source.pixels).as_mut_slice(),
+                        );
+                    }
+
+                    // Integer factors only repeat pixels, which doesn't filter them again.
+                    let prescaled = slot.constrainted && scale.fract() != 0.0;
+
+                    let (slot_width, slot_height) = if prescaled {
+                        let (width, height) = (scaled(slot.width), scaled(slot.height));
+
+                        (
+                            width.min(u32::MAX as f64) as u32,
+                            height.min(u32::MAX as f64) as u32,
+                        )
+                    } else {
+                        (slot.width, slot.height)
                     };
 
-                    let position = if slot.constrainted {
-                        image.width = slot.width;
-                        image.height = slot.height;
+                    let resampled = if slot.constrainted && !only_id {
+                        rgba_len(slot_width, slot_height).map_err(|e| {
+                            anyhow!(
+                                "Failed to resample fragments in slot with id {}: {}",
+                                slot_id,
+                                e
+                            )
+                        })?;
 
-                        *position
+                        Some(resample(&source, slot_width, slot_height, slot.filter))
                     } else {
-                        *position + slot.anchor - fragment.pivot
+                        None
                     };
 
-                    if !only_id {
-                        image.pixels = fragment.image.pixels.clone();
+                    for position in &slot.positions {
+                        let mut image = ImageData {
+                            width: source.width,
+                            height: source.height,
+                            color_type: source.color_type,
+                            ..Default::default()
+                        };
+
+                        let depth = position
+                            .depth
+                            .unwrap_or(slot.depth)
+                            .saturating_add(layer_depth);
+
+                        let flip_x = position.flip_x;
+                        let flip_y = position.flip_y;
+                        let transform = position.transform;
+
+                        let origin = position.point() + slot.anchor;
+
+                        let position = if slot.constrainted {
+                            image.width = slot_width;
+                            image.height = slot_height;
+
+                            position.point()
+                        } else {
+                            let mut pivot = pivot;
+
+                            if flip_x {
+                                pivot.x = source.width as f32 - pivot.x;
+                            }
+
+                            if flip_y {
+                                pivot.y = source.height as f32 - pivot.y;
+                            }
+
+                            origin - pivot
+                        };
+
+                        if !only_id {
+                            image.pixels = match &resampled {
+                                Some(resampled) => resampled.pixels.clone(),
+                                None => source.pixels.clone(),
+                            };
+                        }
+
+                        let mut piece = RenderPiece {
+                            id: *fragment_id,
+                            layer,
+                            slot: Some(*slot_id),
+                            position,
+                            depth,
+                            flip_x,
+                            flip_y,
+                            origin,
+                            transform,
+                            filter: slot.filter,
+                            blend_mode: fragment.blend_mode.unwrap_or(slot.blend_mode),
+                            opacity: style
+                                .and_then(|style| style.opacity)
+                                .unwrap_or(slot.opacity),
+                            clip: slot.clip,
+                            tint: style.and_then(|style| style.tint),
+                            palette_swap,
+                            image,
+                        };
+
+                        if prescaled {
+                            // The image is already scaled, so only its placement is.
+                            piece.position = piece.position * scale;
+                            piece.origin = piece.origin * scale;
+                        } else {
+                            piece.scale(scale);
+                        }
+
+                        slots.push(piece);
                     }
-
-                    slots.push(RenderPiece {
-                        id: *fragment_id,
-                        position,
-                        image,
-                    });
                 }
             }
         }
 
-        let doll = (!doll.image.is_empty()).then(|| {
+        slots.sort_by_key(|piece| piece.depth);
+
+        let doll_image = self
+            .resolve(&doll.image, &doll.path)
+            .map_err(|e| anyhow!("Failed to load image of doll with id {}: {}", doll.id(), e))?;
+
+        if let Some(slot_id) = clipped_to_doll.filter(|_| doll_image.is_empty()) {
+            bail!(
+                "Slot with id {} is clipped to doll with id {}, which has no image",
+                slot_id,
+                doll.id()
+            );
+        }
+
+        let doll = if doll_image.is_empty() {
+            None
+        } else {
             let image = if only_id {
-                ImageData {
-                    width: doll.image.width,
-                    height: doll.image.height,
-                    color_type: doll.image.color_type,
+                doll_image.validate().map(|_| ImageData {
+                    width: doll_image.width,
+                    height: doll_image.height,
+                    color_type: doll_image.color_type,
                     ..Default::default()
-                }
+                })
             } else {
-                doll.image.clone()
-            };
-
-            RenderPiece {
+                doll_image.to_rgba()
+            }
+            .map_err(|e| {
+                anyhow!(
+                    "Doll with id {} contains invalid image data: {}",
+                    doll.id(),
+                    e
+                )
+            })?;
+
+            let mut piece = RenderPiece {
                 id: doll.id(),
+                layer: None,
+                slot: None,
                 position: doll.offset,
+                depth: doll.depth,
+                flip_x: false,
+                flip_y: false,
+                origin: doll.offset,
+                transform: Transform::default(),
+                filter: Filter::default(),
+                blend_mode: BlendMode::default(),
+                opacity: 1.0,
+                clip: None,
+                tint: None,
+                palette_swap: None,
                 image,
-            }
-        });
+            };
+
+            piece.scale(scale);
+
+            Some(piece)
+        };
 
         Ok(RenderMaterial {
             width,
@@ -321,29 +678,26 @@ impl PaperdollFactory {
         })
     }
 
-    /// Returns the structure of the given paperdoll.
-    ///
-    /// Calls [`Self::analyse`] under the hood.
-    pub fn analyse_paperdoll(
-        &self,
-        paperdoll: &Paperdoll,
-        only_id: bool,
-    ) -> Result<RenderMaterial> {
-        self.analyse(paperdoll.doll, &paperdoll.slot_map, only_id)
-    }
-
     /// Returns a builder to construct [`Paperdoll`].
-    pub fn builder(&self) -> PaperdollBuilder {
+    pub fn builder(&self) -> PaperdollBuilder<'_> {
         PaperdollBuilder::new(&self.dolls, &self.slots, &self.fragments)
+            .with_palettes(&self.palettes)
+    }
+
+    /// Removes all images in the [render cache](Self::set_cache_limit).
+    ///
+    /// The cache is cleared automatically when dolls, slots, fragments, or palettes are modified through this factory.
+    pub fn clear_cache(&self) {
+        self.lock_cache().clear();
     }
 
     /// Returns an iterator over all ids of dolls.
-    pub fn dolls(&self) -> Iter<u32, Doll> {
+    pub fn dolls(&self) -> Iter<'_, u32, Doll> {
         self.dolls.iter()
     }
 
     /// Returns an iterator over all ids of fragments.
-    pub fn fragments(&self) -> Iter<u32, Fragment> {
+    pub fn fragments(&self) -> Iter<'_, u32, Fragment> {
         self.fragments.iter()
     }
 
@@ -353,7 +707,11 @@ impl PaperdollFactory {
     }
 
     /// Returns a mutable reference to the doll with the given id.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit), as the doll may be modified.
     pub fn get_doll_mut(&mut self, id: u32) -> Option<&mut Doll> {
+        self.clear_cache();
+
         self.dolls.get_mut(&id)
     }
 
@@ -363,25 +721,118 @@ impl PaperdollFactory {
     }
 
     /// Returns a mutable reference to the fragment with the given id.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit), as the fragment may be modified.
     pub fn get_fragment_mut(&mut self, id: u32) -> Option<&mut Fragment> {
+        self.clear_cache();
+
         self.fragments.get_mut(&id)
     }
 
+    /// Returns a reference to the palette with the given id.
+    pub fn get_palette(&self, id: u32) -> Option<&Palette> {
+        self.palettes.get(&id)
+    }
+
+    /// Returns a mutable reference to the palette with the given id.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit), as the palette may be modified.
+    pub fn get_palette_mut(&mut self, id: u32) -> Option<&mut Palette> {
+        self.clear_cache();
+
+        self.palettes.get_mut(&id)
+    }
+
     /// Returns a reference to the slot with the given id.
     pub fn get_slot(&self, id: u32) -> Option<&Slot> {
         self.slots.get(&id)
     }
 
     /// Returns a mutable reference to the slot with the given id.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit), as the slot may be modified.
     pub fn get_slot_mut(&mut self, id: u32) -> Option<&mut Slot> {
+        self.clear_cache();
+
         self.slots.get_mut(&id)
     }
 
+    /// Decodes the images of all dolls, fragments and layers from their paths.
+    ///
+    /// Paths are relative to the given directory, and can't leave it. Assets with an empty path are skipped.
+    /// PNG, JPEG, GIF, BMP and WebP images are supported.
+    ///
+    /// Works like [`Self::load_images_with`] with a [`FsLoader`].
+    #[cfg(feature = "decode")]
+    pub fn load_images(&mut self, base_dir: impl AsRef<Path>) -> Vec<(Asset, anyhow::Error)> {
+        self.load_images_with(&FsLoader::new(base_dir.as_ref()))
+    }
+
+    /// Loads the images of all dolls, fragments and layers from their paths using the given loader.
+    ///
+    /// Assets with an empty path are skipped.
+    ///
+    /// Loading doesn't stop at the first failure.
+    /// Returns the assets which failed to load with their errors, which is empty if all images are loaded.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit).
+    pub fn load_images_with(&mut self, loader: &dyn AssetLoader) -> Vec<(Asset, anyhow::Error)> {
+        let mut errors = vec![];
+
+        for (id, doll) in &mut self.dolls {
+            if doll.path.is_empty() {
+                continue;
+            }
+
+            match loader.load(&doll.path) {
+                Ok(image) => doll.image = image,
+                Err(err) => errors.push((Asset::Doll(*id), err)),
+            }
+        }
+
+        for (id, fragment) in &mut self.fragments {
+            if !fragment.path.is_empty() {
+                match loader.load(&fragment.path) {
+                    Ok(image) => fragment.image = image,
+                    Err(err) => errors.push((Asset::Fragment(*id), err)),
+                }
+            }
+
+            for (index, layer) in fragment.layers.iter_mut().enumerate() {
+                if layer.path.is_empty() {
+                    continue;
+                }
+
+                match loader.load(&layer.path) {
+                    Ok(image) => layer.image = image,
+                    Err(err) => errors.push((
+                        Asset::Layer {
+                            fragment: *id,
+                            index,
+                        },
+                        err,
+                    )),
+                }
+            }
+        }
+
+        self.clear_cache();
+
+        errors
+    }
+
+    /// Returns an iterator over all ids of palettes.
+    pub fn palettes(&self) -> Iter<'_, u32, Palette> {
+        self.palettes.iter()
+    }
+
     /// Removes the doll with the given id from the factory.
     ///
     /// Returns the removed doll if it was previously in the factory, otherwise returns [`None`].
     pub fn remove_doll(&mut self, id: u32) -> Option<Doll> {
         if let Some(doll) = self.dolls.remove(&id) {
+            self.clear_cache();
+
             self.doll_id_factory.remove(id);
 
             for slot_id in &doll.slots {
@@ -399,6 +850,8 @@ impl PaperdollFactory {
     /// Returns the removed fragment if it was previously in the factory, otherwise returns [`None`].
     pub fn remove_fragment(&mut self, id: u32) -> Option<Fragment> {
         if let Some(fragment) = self.fragments.remove(&id) {
+            self.clear_cache();
+
             self.fragment_id_factory.remove(id);
 
             for slot in &mut self.slots.values_mut() {
@@ -417,11 +870,34 @@ impl PaperdollFactory {
         }
     }
 
+    /// Removes the palette with the given id from the factory.
+    ///
+    /// Returns the removed palette if it was previously in the factory, otherwise returns [`None`].
+    pub fn remove_palette(&mut self, id: u32) -> Option<Palette> {
+        if let Some(palette) = self.palettes.remove(&id) {
+            self.clear_cache();
+
+            self.palette_id_factory.remove(id);
+
+            for fragment in &mut self.fragments.values_mut() {
+                if fragment.palette == Some(id) {
+                    fragment.palette = None;
+                }
+            }
+
+            Some(palette)
+        } else {
+            None
+        }
+    }
+
     /// Removes the slot with the given id from the factory.
     ///
     /// Returns the removed slot if it was previously in the factory, otherwise returns [`None`].
     pub fn remove_slot(&mut self, id: u32) -> Option<Slot> {
         if let Some(slot) = self.slots.remove(&id) {
+            self.clear_cache();
+
             self.slot_id_factory.remove(id);
 
             for doll in &mut self.dolls.values_mut() {
@@ -443,138 +919,498 @@ impl PaperdollFactory {
     /// - `doll`: The id of the doll to be displayed.
     /// - `slot_map`: A map with the id of slot as key and the id of fragment which is used in this slot as value.
     pub fn render(&self, doll: u32, slot_map: &HashMap<u32, u32>) -> Result<ImageData> {
-        let material = self.analyse(doll, slot_map, false)?;
+        let paperdoll = Paperdoll {
+            doll,
+            slot_map: slot_map.clone(),
+            ..Default::default()
+        };
 
-        let pixels = vec![0; (material.width * material.height * 4) as usize];
+        self.render_with_options(&paperdoll, &RenderOptions::default())
+    }
+
+    /// Renders all the given paperdolls, using the given options.
+    ///
+    /// Paperdolls are rendered in parallel if the `rayon` feature is enabled.
+    /// Returns a result for each paperdoll, in the same order, so that a failed paperdoll doesn't affect the others.
+    pub fn render_batch(
+        &self,
+        paperdolls: &[Paperdoll],
+        options: &RenderOptions,
+    ) -> Vec<Result<ImageData>> {
+        let mut images = vec![];
+
+        let results = self.render_batch_into(paperdolls, options, &mut images);
+
+        results
+            .into_iter()
+            .zip(images)
+            .map(|(result, image)| result.map(|_| image))
+            .collect()
+    }
+
+    /// Renders all the given paperdolls into `images`, using the given options.
+    ///
+    /// Works like [`Self::render_batch`], but reuses the pixel buffers of `images` whenever possible.
+    /// `images` is resized to the number of paperdolls, and the image of each paperdoll is at the same index.
+    /// Images of the failed paperdolls are left empty.
+    pub fn render_batch_into(
+        &self,
+        paperdolls: &[Paperdoll],
+        options: &RenderOptions,
+        images: &mut Vec<ImageData>,
+    ) -> Vec<Result<()>> {
+        images.resize_with(paperdolls.len(), ImageData::default);
 
-        let mut image = ImageData {
-            width: material.width,
-            height: material.height,
-            color_type: ColorType::Rgba,
-            pixels,
+        let render = |(paperdoll, image): (&Paperdoll, &mut ImageData)| {
+            if let Some(cached) = self.lock_cache().get(paperdoll, options) {
+                *image = cached;
+
+                return Ok(());
+            }
+
+            let result = self
+                .analyse_with_options(paperdoll, options)
+                .and_then(|material| self.compose_framed(material, options, image));
+
+            match result {
+                Ok(_) => {
+                    self.lock_cache().insert(paperdoll, options, image);
+
+                    Ok(())
+                }
+                Err(err) => {
+                    image.width = 0;
+                    image.height = 0;
+
+                    match Arc::get_mut(&mut image.pixels) {
+                        Some(pixels) => pixels.clear(),
+                        None => image.pixels = Arc::default(),
+                    }
+
+                    Err(err)
+                }
+            }
         };
 
-        if let Some(doll) = material.doll {
-            copy_pixels(
-                &mut image,
-                &doll.image,
-                doll.position.x as isize,
-                doll.position.y as isize,
-            );
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            paperdolls
+                .par_iter()
+                .zip(images.par_iter_mut())
+                .map(render)
+                .collect()
         }
 
-        for slot in material.slots {
-            copy_pixels(
-                &mut image,
-                &slot.image,
-                slot.position.x as isize,
-                slot.position.y as isize,
-            );
+        #[cfg(not(feature = "rayon"))]
+        {
+            paperdolls
+                .iter()
+                .zip(images.iter_mut())
+                .map(render)
+                .collect()
         }
+    }
 
-        return Ok(image);
+    /// Renders the given paperdoll using the given options, and returns the area of the doll covered by the image.
+    ///
+    /// The top left corner of the doll is the origin of the area,
+    /// so the doll is placed at `(-area.x, -area.y)` in the image.
+    /// The area differs from the doll if the [framing](RenderOptions::framing) is not [`Framing::Doll`].
+    ///
+    /// Unlike [`Self::render_with_options`], the [render cache](Self::set_cache_limit) is not used.
+    pub fn render_framed(
+        &self,
+        paperdoll: &Paperdoll,
+        options: &RenderOptions,
+    ) -> Result<(ImageData, Rect)> {
+        let material = self.analyse_with_options(paperdoll, options)?;
 
-        fn copy_pixels(dst: &mut ImageData, src: &ImageData, dx: isize, dy: isize) {
-            if src.is_empty() {
-                return;
-            }
+        let mut image = ImageData::default();
 
-            if dx >= dst.width as isize
-                || (dx + src.width as isize) < 0
-                || dy >= dst.height as isize
-                || (dy + src.height as isize) < 0
-            {
-                return;
-            }
+        let area = self.compose_framed(material, options, &mut image)?;
 
-            let dst_row_len = (dst.width * 4) as usize;
-            let src_row_len = (src.width * 4) as usize;
+        Ok((image, area))
+    }
 
-            let sx = if dx >= 0 { 0 } else { dx.abs_diff(0) };
-            let sy = if dy >= 0 { 0 } else { dy.abs_diff(0) };
+    /// Renders the given paperdoll into a buffer provided by the caller, using the given options.
+    ///
+    /// Nothing is allocated for the result, which makes it suitable for updating textures or packing many paperdolls into an atlas.
+    ///
+    /// # Arguments
+    ///
+    /// - `paperdoll`: The paperdoll to be rendered.
+    /// - `options`: The options used for rendering.
+    /// - `buffer`: RGBA pixels to render into.
+    /// - `stride`: The number of bytes between the starts of two rows in `buffer`.
+    /// - `offset`: The position in `buffer` where the top left corner of the rendered area is placed, in pixels.
+    /// - `clip`: The area of the doll to be rendered. The whole doll is rendered if [`None`].
+    ///
+    /// The target area in `buffer` is overwritten, other pixels are left untouched.
+    /// Returns the area of the doll which has been rendered, that is `clip` limited to the size of the doll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll can't be analysed, see [`Self::analyse_paperdoll`].
+    /// - Will return an error if the target area doesn't fit in `buffer`.
+    /// - Will return an error if the buffers used for clipping can't be allocated.
+    pub fn render_into(
+        &self,
+        paperdoll: &Paperdoll,
+        options: &RenderOptions,
+        buffer: &mut [u8],
+        stride: usize,
+        offset: (u32, u32),
+        clip: Option<Rect>,
+    ) -> Result<Rect> {
+        let material = self.analyse_with_options(paperdoll, options)?;
 
-            let dx = 0.max(dx) as usize;
-            let dy = 0.max(dy) as usize;
+        let bounds = Rect::new(0, 0, material.width, material.height);
 
-            let copy_width = (src.width as usize - sx).min(dst.width as usize - dx) * 4;
+        let rect = clip.map_or(bounds, |clip| clip.intersect(&bounds));
 
-            let mut dst_cursor = dy * dst_row_len + dx * 4;
-            let mut src_cursor = sy * src_row_len + sx * 4;
+        if rect.is_empty() {
+            return Ok(rect);
+        }
 
-            while dst_cursor < dst.pixels.len() && src_cursor < src.pixels.len() {
-                blend_alpha_over(
-                    &mut dst.pixels[dst_cursor..dst_cursor + copy_width],
-                    &src.pixels[src_cursor..src_cursor + copy_width],
-                );
+        let row_len = rect.width as usize * 4;
 
-                dst_cursor += dst_row_len;
-                src_cursor += src_row_len;
-            }
+        let left = (offset.0 as usize).checked_mul(4);
 
-            fn blend_alpha_over(dst: &mut [u8], src: &[u8]) {
-                assert_eq!(
-                    dst.len(),
-                    src.len(),
-                    "destination and source buffer must have same length."
-                );
-
-                let mut cursor = 0;
-
-                while cursor < dst.len() {
-                    let alpha = src[cursor + 3]
-                        + (dst[cursor + 3] as f32 * (1.0 - src[cursor + 3] as f32 / 255.0)) as u8;
-
-                    if alpha != 0 {
-                        dst[cursor] = blend(
-                            dst[cursor],
-                            src[cursor],
-                            dst[cursor + 3],
-                            src[cursor + 3],
-                            alpha,
-                        );
+        if left
+            .and_then(|left| left.checked_add(row_len))
+            .is_none_or(|right| right > stride)
+        {
+            bail!("Rendered area exceeds the stride of the buffer");
+        }
 
-                        dst[cursor + 1] = blend(
-                            dst[cursor + 1],
-                            src[cursor + 1],
-                            dst[cursor + 3],
-                            src[cursor + 3],
-                            alpha,
-                        );
+        let start = (offset.1 as usize)
+            .checked_mul(stride)
+            .zip(left)
+            .and_then(|(top, left)| top.checked_add(left));
 
-                        dst[cursor + 2] = blend(
-                            dst[cursor + 2],
-                            src[cursor + 2],
-                            dst[cursor + 3],
-                            src[cursor + 3],
-                            alpha,
-                        );
-                    }
+        let range = start
+            .and_then(|start| {
+                let end = (rect.height as usize - 1)
+                    .checked_mul(stride)?
+                    .checked_add(start)?
+                    .checked_add(row_len)?;
 
-                    dst[cursor + 3] = alpha;
+                Some((start, end))
+            })
+            .filter(|(_, end)| *end <= buffer.len());
 
-                    cursor += 4;
-                }
+        let Some((start, end)) = range else {
+            bail!("Rendered area exceeds the end of the buffer");
+        };
 
-                fn blend(dc: u8, sc: u8, da: u8, sa: u8, alpha: u8) -> u8 {
-                    let da = da as f32 / 255.0;
-                    let sa = sa as f32 / 255.0;
-                    let alpha = alpha as f32 / 255.0;
+        let mut canvas = Canvas {
+            width: rect.width,
+            height: rect.height,
+            stride,
+            pixels: &mut buffer[start..end],
+        };
 
-                    ((sc as f32 * sa + dc as f32 * da * (1.0 - sa)) / alpha) as u8
-                }
-            }
-        }
+        canvas.clear();
+
+        self.compose_canvas(material.crop(rect), options, &mut canvas)?;
+
+        Ok(rect)
     }
 
     /// Returns the image data to render the given paperdoll.
     ///
-    /// Calls [`Self::render`] under the hood.
+    /// Works like [`Self::render`], with the [styles](crate::SlotStyle) and the [palette](crate::Palette) of the paperdoll applied.
     pub fn render_paperdoll(&self, paperdoll: &Paperdoll) -> Result<ImageData> {
-        self.render(paperdoll.doll, &paperdoll.slot_map)
+        self.render_with_options(paperdoll, &RenderOptions::default())
+    }
+
+    /// Renders the given paperdoll like [`Self::render_paperdoll`], and writes the image as a PNG file at the given path.
+    ///
+    /// The file is created, or truncated if it exists.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll can't be rendered.
+    /// - Will return an error if the file can't be written.
+    #[cfg(feature = "encode")]
+    pub fn render_to_png(&self, paperdoll: &Paperdoll, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+
+        let image = self.render_paperdoll(paperdoll)?;
+
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Failed to create {}: {}", path.display(), e))?;
+
+        let mut writer = BufWriter::new(file);
+
+        image
+            .write_png(&mut writer)
+            .and_then(|_| Ok(writer.flush()?))
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Returns the image data to render the given paperdoll, using the given options.
+    ///
+    /// See [`RenderOptions`] for what can be configured.
+    pub fn render_with_options(
+        &self,
+        paperdoll: &Paperdoll,
+        options: &RenderOptions,
+    ) -> Result<ImageData> {
+        if let Some(image) = self.lock_cache().get(paperdoll, options) {
+            return Ok(image);
+        }
+
+        let (image, _) = self.render_framed(paperdoll, options)?;
+
+        self.lock_cache().insert(paperdoll, options, &image);
+
+        Ok(image)
+    }
+
+    /// Sets the maximum size in bytes of the render cache. `0` disables the cache, which is the default.
+    ///
+    /// The cache keeps the images rendered by [`Self::render`], [`Self::render_paperdoll`], [`Self::render_with_options`], and [`Self::render_batch`],
+    /// so that paperdolls with the same configuration and options are rendered only once.
+    /// The least recently used images are dropped when the cache exceeds the limit.
+    pub fn set_cache_limit(&mut self, max_bytes: usize) {
+        self.lock_cache().set_max_bytes(max_bytes);
+    }
+
+    /// Sets the loader used to resolve the paths of dolls, fragments and layers lazily.
+    ///
+    /// When a doll, a fragment or a layer with an empty image is analysed or rendered, its image is loaded from its path on first use.
+    /// Loaded images are kept by the factory and shared by later renders, while the images stored in dolls, fragments and layers are left untouched.
+    /// Use [`Self::load_images_with`] instead to load all images at once.
+    ///
+    /// Forgets the images loaded by the previous loader, and clears the [render cache](Self::set_cache_limit).
+    pub fn set_loader(&mut self, loader: impl AssetLoader + 'static) {
+        self.loader = Some(Box::new(loader));
+
+        self.unload_images();
+    }
+
+    /// Returns the structure of the paperdoll to be rendered with the given options.
+    pub(crate) fn analyse_with_options(
+        &self,
+        paperdoll: &Paperdoll,
+        options: &RenderOptions,
+    ) -> Result<RenderMaterial> {
+        if !(options.scale.is_finite() && options.scale > 0.0) {
+            bail!("Invalid scale factor: {}", options.scale);
+        }
+
+        let material = self.analyse_scaled(paperdoll, false, options.scale)?;
+
+        rgba_len(material.width, material.height).map_err(|e| {
+            anyhow!(
+                "Scale factor {} is too large for a doll of {}x{} pixels: {}",
+                options.scale,
+                material.width as f32 / options.scale,
+                material.height as f32 / options.scale,
+                e
+            )
+        })?;
+
+        Ok(material)
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, RenderCache> {
+        self.cache.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn lock_loaded(&self) -> MutexGuard<'_, HashMap<String, ImageData>> {
+        self.loaded.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the given image, or the image at the path resolved by the [loader](Self::set_loader) if the image is empty.
+    ///
+    /// Images resolved by the loader are kept, so each path is loaded only once.
+    pub(crate) fn resolve(&self, image: &ImageData, path: &str) -> Result<ImageData> {
+        let Some(loader) = &self.loader else {
+            return Ok(image.clone());
+        };
+
+        if !image.is_empty() || path.is_empty() {
+            return Ok(image.clone());
+        }
+
+        if let Some(image) = self.lock_loaded().get(path) {
+            return Ok(image.clone());
+        }
+
+        // Doesn't hold the lock while loading, so that other threads can render meanwhile.
+        let image = loader.load(path)?;
+
+        self.lock_loaded().insert(path.to_owned(), image.clone());
+
+        Ok(image)
+    }
+
+    /// Composes the material into the given image, covering the area of the doll decided by the [framing](RenderOptions::framing).
+    ///
+    /// Returns the area of the doll covered by the image.
+    fn compose_framed(
+        &self,
+        material: RenderMaterial,
+        options: &RenderOptions,
+        image: &mut ImageData,
+    ) -> Result<Rect> {
+        let bounds = Rect::new(0, 0, material.width, material.height);
+
+        let area = match options.framing {
+            Framing::Doll => bounds,
+            Framing::Expand => bounds.union(&material.bounds()),
+            Framing::Crop => material.alpha_bounds(),
+        };
+
+        let material = if area == bounds {
+            material
+        } else {
+            material.crop(area)
+        };
+
+        self.compose_into(material, options, image)?;
+
+        if options.framing != Framing::Crop {
+            return Ok(area);
+        }
+
+        // The alpha bounds of the material is only an estimation, the content is cropped again after compositing.
+        let content = image.alpha_bounds();
+
+        if content != Rect::new(0, 0, image.width, image.height) {
+            *image = image.cropped(content);
+        }
+
+        Ok(Rect::new(
+            area.x + content.x,
+            area.y + content.y,
+            content.width,
+            content.height,
+        ))
+    }
+
+    /// Composes the material into the given image, reusing its pixel buffer.
+    ///
+    /// The image is left untouched if its pixels can't be allocated,
+    /// but may be partly composed if the buffers used for clipping can't.
+    pub(crate) fn compose_into(
+        &self,
+        material: RenderMaterial,
+        options: &RenderOptions,
+        image: &mut ImageData,
+    ) -> Result<()> {
+        let len = rgba_len(material.width, material.height)?;
+
+        let reserve = |pixels: &mut Vec<u8>| {
+            pixels
+                .try_reserve_exact(len.saturating_sub(pixels.len()))
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to allocate an image of {}x{} pixels: {}",
+                        material.width,
+                        material.height,
+                        e
+                    )
+                })
+        };
+
+        // Reuses the buffer unless it's shared with other images.
+        match Arc::get_mut(&mut image.pixels) {
+            Some(pixels) => {
+                reserve(pixels)?;
+
+                pixels.clear();
+                pixels.resize(len, 0);
+            }
+            None => {
+                let mut pixels = vec![];
+
+                reserve(&mut pixels)?;
+
+                pixels.resize(len, 0);
+
+                image.pixels = Arc::new(pixels);
+            }
+        }
+
+        image.width = material.width;
+        image.height = material.height;
+        image.color_type = ColorType::Rgba;
+
+        self.compose_canvas(material, options, &mut Canvas::from_image(image))
+    }
+
+    /// Composes the material onto the canvas, which has the same size as the material.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the buffers used for clipping can't be allocated.
+    ///   The canvas may be partly composed in that case.
+    pub(crate) fn compose_canvas(
+        &self,
+        material: RenderMaterial,
+        options: &RenderOptions,
+        canvas: &mut Canvas,
+    ) -> Result<()> {
+        let compositing = options.compositing;
+
+        // Shared by all masks and clipped slots.
+        let mut scratch = vec![];
+
+        let masks = material
+            .slots
+            .iter()
+            .filter_map(|piece| piece.clip)
+            .collect::<HashSet<Clip>>()
+            .into_iter()
+            .map(|clip| {
+                let sources = match clip {
+                    Clip::Doll => material.doll.iter().collect::<Vec<&RenderPiece>>(),
+                    Clip::Slot(id) => material
+                        .slots
+                        .iter()
+                        .filter(|piece| piece.slot == Some(id))
+                        .collect(),
+                };
+
+                let mask = alpha_mask(
+                    material.width,
+                    material.height,
+                    &sources,
+                    compositing,
+                    &mut scratch,
+                )?;
+
+                Ok((clip, mask))
+            })
+            .collect::<Result<HashMap<Clip, Mask>>>()?;
+
+        let mut doll = material.doll;
+
+        for slot in material.slots {
+            if let Some(doll) = doll.take_if(|doll| doll.depth <= slot.depth) {
+                draw_piece(canvas, &doll, compositing);
+            }
+
+            match slot.clip.and_then(|clip| masks.get(&clip)) {
+                Some(mask) => draw_piece_clipped(canvas, &slot, mask, compositing, &mut scratch)?,
+                None => draw_piece(canvas, &slot, compositing),
+            }
+        }
+
+        if let Some(doll) = doll {
+            draw_piece(canvas, &doll, compositing);
+        }
+
+        Ok(())
     }
 
     /// Returns an iterator over all ids of slots.
-    pub fn slots(&self) -> Iter<u32, Slot> {
+    pub fn slots(&self) -> Iter<'_, u32, Slot> {
         self.slots.iter()
     }
 
@@ -585,6 +1421,36 @@ impl PaperdollFactory {
             dolls: self.dolls.values().cloned().collect(),
             slots: self.slots.values().cloned().collect(),
             fragments: self.fragments.values().cloned().collect(),
+            palettes: self.palettes.values().cloned().collect(),
         }
     }
+
+    /// Forgets the images resolved by the [loader](Self::set_loader), so that they are loaded again on next use.
+    ///
+    /// Clears the [render cache](Self::set_cache_limit).
+    pub fn unload_images(&self) {
+        self.lock_loaded().clear();
+
+        self.clear_cache();
+    }
+
+    /// Writes the project as a `ppd` archive, which can be read by [`Self::from_ppd`].
+    ///
+    /// A `ppd` archive is a tar archive containing the manifest as `paperdoll.json`,
+    /// and the images of dolls, fragments and layers as PNG files in the `images` directory.
+    /// Paths in the manifest are replaced with those of the files in the archive.
+    /// Assets with neither an image nor a path are written with an empty path.
+    ///
+    /// Images are stored without loss, except those of [`ColorType::Rgba32F`] which are stored with 16 bits channels.
+    /// Writing the same project always gives the same bytes.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if an image can't be [loaded](Self::set_loader) or encoded.
+    /// - Will return an error if an asset with a path has an empty image, eg. when no loader is set.
+    /// - Will return an error if the writer fails.
+    #[cfg(feature = "ppd")]
+    pub fn write_ppd(&self, writer: impl Write) -> Result<()> {
+        ppd::write(self, writer)
+    }
 }
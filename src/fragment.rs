@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{common::Point, image::ImageData};
+use crate::{blend_mode::BlendMode, common::Point, image::ImageData, layer::Layer};
 
 /// The image assets that you can put into a slot as candidates.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -18,12 +18,27 @@ pub struct Fragment {
     #[serde(default, skip_serializing_if = "Point::is_zero")]
     pub pivot: Point,
 
+    /// The way the fragment is blended with pixels beneath it.
+    /// Overrides [`Slot::blend_mode`](crate::Slot::blend_mode) if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blend_mode: Option<BlendMode>,
+
+    /// The id of the [palette](crate::Palette) the image is drawn with.
+    ///
+    /// Used for palette swapping.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<u32>,
+
     /// The path of the image.
     pub path: String,
 
     /// The data of the image.
     #[serde(skip)]
     pub image: ImageData,
+
+    /// Extra images drawn at their own depths together with the fragment.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<Layer>,
 }
 
 impl Fragment {
@@ -32,8 +47,11 @@ impl Fragment {
             id,
             desc: String::default(),
             pivot: Point::default(),
+            blend_mode: None,
+            palette: None,
             path: String::default(),
             image: ImageData::default(),
+            layers: vec![],
         }
     }
 
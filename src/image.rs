@@ -1,12 +1,102 @@
+#[cfg(feature = "encode")]
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::common::Rect;
+#[cfg(feature = "encode")]
+use crate::encode::encode_png;
+
 /// Types of the color used in `paperdoll`.
-#[derive(Clone, Copy, Debug, Default)]
+///
+/// Channels wider than a byte are stored in native byte order.
+/// Colors are straight (non-premultiplied) and in sRGB space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum ColorType {
+    /// Red, green, blue and alpha channels, 8 bits each.
     #[default]
     Rgba,
+    /// Red, green and blue channels, 8 bits each. Fully opaque.
+    Rgb8,
+    /// A luminance channel of 8 bits. Fully opaque.
+    L8,
+    /// Luminance and alpha channels, 8 bits each.
+    La8,
+    /// Red, green, blue and alpha channels, 16 bits each.
+    Rgba16,
+    /// Red, green, blue and alpha channels, each a 32 bits float from `0.0` to `1.0`.
+    Rgba32F,
+}
+
+impl ColorType {
+    /// Returns the number of bytes used by a pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba => 4,
+            Self::Rgb8 => 3,
+            Self::L8 => 1,
+            Self::La8 => 2,
+            Self::Rgba16 => 8,
+            Self::Rgba32F => 16,
+        }
+    }
+
+    /// Converts a pixel of this type to 8 bits RGBA.
+    fn to_rgba(self, pixel: &[u8]) -> [u8; 4] {
+        let u16 = |i: usize| {
+            let v = u16::from_ne_bytes([pixel[i * 2], pixel[i * 2 + 1]]) as u32;
+
+            ((v * 255 + 32767) / 65535) as u8
+        };
+
+        let f32 = |i: usize| {
+            let v = f32::from_ne_bytes([
+                pixel[i * 4],
+                pixel[i * 4 + 1],
+                pixel[i * 4 + 2],
+                pixel[i * 4 + 3],
+            ]);
+
+            // NaN becomes 0.
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+
+        match self {
+            Self::Rgba => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            Self::Rgb8 => [pixel[0], pixel[1], pixel[2], 255],
+            Self::L8 => [pixel[0], pixel[0], pixel[0], 255],
+            Self::La8 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+            Self::Rgba16 => [u16(0), u16(1), u16(2), u16(3)],
+            Self::Rgba32F => [f32(0), f32(1), f32(2), f32(3)],
+        }
+    }
+}
+
+/// Returns the length of the pixel data of an RGBA image with the given size.
+///
+/// # Errors
+///
+/// - Will return an error if the pixel data can't fit in memory.
+pub(crate) fn rgba_len(width: u32, height: u32) -> Result<usize> {
+    pixels_len(width, height, ColorType::Rgba).ok_or(anyhow!(
+        "Image of {}x{} pixels is too large",
+        width,
+        height
+    ))
+}
+
+/// Returns the length of the pixel data of an image with the given size and color type,
+/// or [`None`] if it can't fit in memory.
+fn pixels_len(width: u32, height: u32, color_type: ColorType) -> Option<usize> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|len| len.checked_mul(color_type.bytes_per_pixel()))
+        .filter(|len| *len <= isize::MAX as usize)
 }
 
 /// The data used in images.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct ImageData {
     /// The width of the image in pixels.
     pub width: u32,
@@ -14,14 +104,317 @@ pub struct ImageData {
     pub height: u32,
 
     /// Type of the color used in the image.
+    ///
+    /// Images of all types can be rendered. They are converted to [`ColorType::Rgba`] while rendering.
     pub color_type: ColorType,
     /// The actual pixel data of the image.
-    pub pixels: Vec<u8>,
+    /// Its length must match the size of the image and the type of the color, see [`Self::validate`].
+    ///
+    /// The pixel data is shared between clones of the image, so cloning an image is cheap.
+    /// Use [`Arc::make_mut`] to modify it, which copies the data only if it's shared.
+    pub pixels: Arc<Vec<u8>>,
 }
 
 impl ImageData {
+    /// Returns the smallest rectangle containing all pixels which are not fully transparent.
+    ///
+    /// Returns an empty rectangle if the whole image is transparent.
+    pub fn alpha_bounds(&self) -> Rect {
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        let bytes = self.color_type.bytes_per_pixel();
+
+        if width == 0 || height == 0 || !self.has_pixels() {
+            return Rect::default();
+        }
+
+        let mut bounds = Rect::default();
+
+        for (y, row) in self
+            .pixels
+            .chunks_exact(width * bytes)
+            .take(height)
+            .enumerate()
+        {
+            let mut opaque = row
+                .chunks_exact(bytes)
+                .enumerate()
+                .filter(|(_, pixel)| self.color_type.to_rgba(pixel)[3] != 0)
+                .map(|(x, _)| x);
+
+            let Some(left) = opaque.next() else {
+                continue;
+            };
+
+            let right = opaque.next_back().unwrap_or(left);
+
+            bounds = bounds.union(&Rect::new(
+                left as i32,
+                y as i32,
+                (right - left + 1) as u32,
+                1,
+            ));
+        }
+
+        bounds
+    }
+
     /// Is this an empty image?
     pub fn is_empty(&self) -> bool {
         self.pixels.is_empty()
     }
+
+    /// Does the pixel data hold at least as many bytes as the size and the type of the color require?
+    pub(crate) fn has_pixels(&self) -> bool {
+        pixels_len(self.width, self.height, self.color_type)
+            .is_some_and(|len| self.pixels.len() >= len)
+    }
+
+    /// Returns a copy of the image converted to [`ColorType::Rgba`].
+    ///
+    /// Images already in [`ColorType::Rgba`] share their pixels with the copy.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is not valid, see [`Self::validate`].
+    pub fn to_rgba(&self) -> Result<Self> {
+        self.validate()?;
+
+        if self.color_type == ColorType::Rgba {
+            return Ok(self.clone());
+        }
+
+        let pixels = self
+            .pixels
+            .chunks_exact(self.color_type.bytes_per_pixel())
+            .flat_map(|pixel| self.color_type.to_rgba(pixel))
+            .collect();
+
+        Ok(Self {
+            width: self.width,
+            height: self.height,
+            color_type: ColorType::Rgba,
+            pixels: Arc::new(pixels),
+        })
+    }
+
+    /// Checks that the length of the pixel data matches the size of the image and the type of the color.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the length of the pixel data doesn't match.
+    pub fn validate(&self) -> Result<()> {
+        let Some(expected) = pixels_len(self.width, self.height, self.color_type) else {
+            bail!(
+                "Image of {}x{} pixels of {:?} is too large",
+                self.width,
+                self.height,
+                self.color_type
+            );
+        };
+
+        if self.pixels.len() != expected {
+            bail!(
+                "Expected {} bytes of pixel data for a {}x{} image of {:?}, found {}",
+                expected,
+                self.width,
+                self.height,
+                self.color_type,
+                self.pixels.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the image as PNG into the given writer.
+    ///
+    /// Images of [`ColorType::Rgba32F`] are written with 16 bits channels, as PNG doesn't support floats.
+    /// Images of other color types are written without loss.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is not valid, see [`Self::validate`].
+    /// - Will return an error if the writer fails.
+    #[cfg(feature = "encode")]
+    pub fn write_png(&self, writer: impl Write) -> Result<()> {
+        encode_png(self, writer)
+    }
+
+    /// Returns a copy of the given area of the RGBA image.
+    /// Parts of the area outside the image are transparent.
+    ///
+    /// Returns an empty image if the area is too large to fit in memory.
+    pub(crate) fn cropped(&self, rect: Rect) -> Self {
+        let Some(len) = pixels_len(rect.width, rect.height, ColorType::Rgba) else {
+            return Self::default();
+        };
+
+        let mut pixels = vec![0; len];
+
+        let area = rect.intersect(&Rect::new(0, 0, self.width, self.height));
+
+        if !area.is_empty() && self.has_pixels() {
+            let row_len = area.width as usize * 4;
+
+            for y in area.y..area.y + area.height as i32 {
+                let src = (y as usize * self.width as usize + area.x as usize) * 4;
+                let dst =
+                    ((y - rect.y) as usize * rect.width as usize + (area.x - rect.x) as usize) * 4;
+
+                pixels[dst..dst + row_len].copy_from_slice(&self.pixels[src..src + row_len]);
+            }
+        }
+
+        Self {
+            width: rect.width,
+            height: rect.height,
+            color_type: self.color_type,
+            pixels: Arc::new(pixels),
+        }
+    }
+
+    /// Returns a copy of the RGBA image mirrored in the given directions.
+    ///
+    /// Invalid images are returned as is.
+    pub(crate) fn flipped(&self, flip_x: bool, flip_y: bool) -> Self {
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        if !self.has_pixels() {
+            return self.clone();
+        }
+
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+
+        for y in 0..height {
+            let sy = if flip_y { height - 1 - y } else { y };
+
+            let row = &self.pixels[sy * width * 4..(sy + 1) * width * 4];
+
+            if flip_x {
+                row.chunks_exact(4)
+                    .rev()
+                    .for_each(|pixel| pixels.extend_from_slice(pixel));
+            } else {
+                pixels.extend_from_slice(row);
+            }
+        }
+
+        Self {
+            width: self.width,
+            height: self.height,
+            color_type: self.color_type,
+            pixels: Arc::new(pixels),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color_type: ColorType, pixels: Vec<u8>) -> ImageData {
+        ImageData {
+            width,
+            height,
+            color_type,
+            pixels: Arc::new(pixels),
+        }
+    }
+
+    fn rgba(image: ImageData) -> Vec<u8> {
+        image.to_rgba().unwrap().pixels.to_vec()
+    }
+
+    #[test]
+    fn converts_8_bits_types() {
+        assert_eq!(
+            rgba(image(2, 1, ColorType::Rgb8, vec![1, 2, 3, 4, 5, 6])),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+
+        assert_eq!(
+            rgba(image(2, 1, ColorType::L8, vec![0, 200])),
+            [0, 0, 0, 255, 200, 200, 200, 255]
+        );
+
+        assert_eq!(
+            rgba(image(2, 1, ColorType::La8, vec![10, 0, 20, 128])),
+            [10, 10, 10, 0, 20, 20, 20, 128]
+        );
+    }
+
+    #[test]
+    fn rgba_images_share_their_pixels() {
+        let source = image(1, 1, ColorType::Rgba, vec![1, 2, 3, 4]);
+
+        assert!(Arc::ptr_eq(
+            &source.to_rgba().unwrap().pixels,
+            &source.pixels
+        ));
+    }
+
+    #[test]
+    fn rounds_16_bits_channels() {
+        // Midpoints between two 8 bits values lie at 128.5, 65406.5 and so on.
+        let pixels = [0u16, 128, 129, 65535, 32896, 65406, 65407, 1]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+
+        assert_eq!(
+            rgba(image(2, 1, ColorType::Rgba16, pixels)),
+            [0, 0, 1, 255, 128, 254, 255, 0]
+        );
+    }
+
+    #[test]
+    fn clamps_float_channels() {
+        let pixels = [0.0f32, 0.5, 1.0, 2.0, f32::NAN, -1.0, 0.25, f32::INFINITY]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect();
+
+        assert_eq!(
+            rgba(image(2, 1, ColorType::Rgba32F, pixels)),
+            [0, 128, 255, 255, 0, 0, 64, 255]
+        );
+    }
+
+    #[test]
+    fn mismatched_lengths_are_errors() {
+        let err = image(3, 2, ColorType::La8, vec![0; 11])
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Expected 12 bytes of pixel data for a 3x2 image of La8, found 11"
+        );
+
+        assert!(image(3, 2, ColorType::La8, vec![0; 13]).to_rgba().is_err());
+        assert!(image(3, 2, ColorType::La8, vec![0; 12]).to_rgba().is_ok());
+    }
+
+    #[test]
+    fn huge_sizes_do_not_overflow() {
+        let huge = image(u32::MAX, u32::MAX, ColorType::Rgba32F, vec![0; 16]);
+
+        assert!(huge
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("too large"));
+        assert!(huge.alpha_bounds().is_empty());
+
+        let huge = ImageData {
+            color_type: ColorType::Rgba,
+            ..huge
+        };
+
+        assert_eq!(huge.flipped(true, true), huge);
+        assert!(huge.cropped(Rect::new(0, 0, u32::MAX, u32::MAX)).is_empty());
+    }
 }
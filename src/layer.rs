@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{is_default, Point},
+    image::ImageData,
+};
+
+/// An extra image of a [fragment](crate::Fragment) which is drawn at its own depth.
+///
+/// Useful for items that need one part behind other slots and another part in front of them, eg. a hat with a brim.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Layer {
+    /// The description of the layer.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub desc: String,
+
+    /// The coordinate of the pivot point of the layer.
+    /// The top left corner of the layer is the origin.
+    ///
+    /// Used in non-constrainted mode.
+    #[serde(default, skip_serializing_if = "Point::is_zero")]
+    pub pivot: Point,
+
+    /// The draw order of the layer, relative to the depth of the slot the fragment is placed in.
+    ///
+    /// See [`Slot::depth`](crate::Slot::depth).
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub depth: i32,
+
+    /// The path of the image.
+    pub path: String,
+
+    /// The data of the image.
+    #[serde(skip)]
+    pub image: ImageData,
+}
@@ -2,7 +2,7 @@
 //!
 //! - It's 2D.
 //! - It's pixel-based. Vector images and basic shapes are not supported in the current version.
-//! - It's stationary. Animations and transformations are not supported in the current version.
+//! - It's stationary. Animations are not supported in the current version.
 //!
 //! Latest version: 1.
 //!
@@ -32,36 +32,81 @@
 //!
 //! - **Non-constrainted**. Slots and fragments are connected like mortises and tenons. There is an anchor point inside a slot. When a fragment is placed into a slot, the pivot point of that fragment will be placed in the same position as the anchor point. The fragment remains its original size and resizing will never happen.
 //!
+//! Each position of a slot can optionally mirror, rotate, or scale the fragment around the anchor point. See [`Position`].
+//!
 //! ![core-concept](https://raw.githubusercontent.com/fralonra/paperdoll/master/doc/paperdoll-concept.png)
 //!
 //! ### Examples
 //!
 //! See [`PaperdollFactory`].
 
+mod asset;
+mod blend;
+mod blend_mode;
 mod builder;
+mod clip;
 mod common;
+mod compositor;
+#[cfg(feature = "decode")]
+mod decode;
 mod doll;
+#[cfg(feature = "encode")]
+mod encode;
 mod factory;
 mod fragment;
 mod id_factory;
 mod image;
+mod layer;
 mod manifest;
 mod meta;
+mod palette;
 mod paperdoll;
+mod position;
+#[cfg(feature = "ppd")]
+mod ppd;
+mod render_cache;
 mod render_material;
+mod render_options;
+mod renderer;
+mod resample;
 mod slot;
+mod slot_style;
+mod tint;
+mod transform;
 
 pub use crate::paperdoll::Paperdoll;
+#[cfg(feature = "decode")]
+pub use asset::FsLoader;
+pub use asset::{Asset, AssetLoader, MemoryLoader};
+pub use blend_mode::BlendMode;
 pub use builder::PaperdollBuilder;
-pub use common::Point;
+pub use clip::Clip;
+pub use common::{Point, Rect};
 pub use doll::Doll;
 pub use factory::PaperdollFactory;
 pub use fragment::Fragment;
 pub use image::{ColorType, ImageData};
+pub use layer::Layer;
 pub use manifest::Manifest;
 pub use meta::Meta;
+pub use palette::Palette;
+pub use position::Position;
 pub use render_material::{RenderMaterial, RenderPiece};
+pub use render_options::{Compositing, Framing, RenderOptions};
+pub use renderer::Renderer;
+pub use resample::Filter;
 pub use slot::Slot;
+pub use slot_style::SlotStyle;
+pub use tint::Tint;
+pub use transform::Transform;
+
+/// Internals used by the benchmarks. Not part of the public API.
+#[doc(hidden)]
+pub mod bench {
+    #[cfg(feature = "simd")]
+    pub use crate::blend::blend_normal_chunked;
+    pub use crate::blend::blend_scalar;
+}
 
 /// The latest version of paperdoll.
 pub const VERSION: u32 = 1;
@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{doll::Doll, fragment::Fragment, meta::Meta, slot::Slot};
+use crate::{doll::Doll, fragment::Fragment, meta::Meta, palette::Palette, slot::Slot};
 
 /// A manifest for a `paperdoll` project.
 ///
 /// Serves as an entry point to everything used in the model.
-/// Including dolls, slots, and fragments.
+/// Including dolls, slots, fragments, and palettes.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Manifest {
     /// The meta data of the project.
@@ -17,4 +17,7 @@ pub struct Manifest {
     pub slots: Vec<Slot>,
     /// All the fragments in the project.
     pub fragments: Vec<Fragment>,
+    /// All the palettes in the project.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub palettes: Vec<Palette>,
 }
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A named list of colors used for palette swapping.
+///
+/// When a [fragment](crate::Fragment) declares a source palette and a target palette is picked for it,
+/// every pixel whose color exactly matches the `n`th color of the source palette is replaced with the `n`th color of the target palette.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Palette {
+    id: u32,
+
+    /// The name of the palette.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    /// The RGB colors of the palette.
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    pub(crate) fn new(id: u32) -> Self {
+        Self {
+            id,
+            name: String::default(),
+            colors: vec![],
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Replaces colors of this palette found in the RGBA pixels with the colors at the same index of the target palette.
+    /// The alpha channel is left untouched.
+    pub(crate) fn remap(&self, target: &Palette, pixels: &mut [u8]) {
+        let map = self
+            .colors
+            .iter()
+            .zip(&target.colors)
+            .filter(|(from, to)| from != to)
+            .map(|(from, to)| (*from, *to))
+            .collect::<HashMap<[u8; 3], [u8; 3]>>();
+
+        if map.is_empty() {
+            return;
+        }
+
+        for pixel in pixels.chunks_exact_mut(4) {
+            if pixel[3] == 0 {
+                continue;
+            }
+
+            if let Some(color) = map.get(&[pixel[0], pixel[1], pixel[2]]) {
+                pixel[..3].copy_from_slice(color);
+            }
+        }
+    }
+}
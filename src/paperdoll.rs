@@ -1,12 +1,23 @@
 use std::collections::HashMap;
 
+use crate::slot_style::SlotStyle;
+
 /// A paper doll model.
 ///
 /// See [`crate::PaperdollFactory`] for examples.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Paperdoll {
     /// The id of [doll](crate::Doll) to use.
     pub doll: u32,
 
     /// A map with the id of [slot](crate::Slot) as key and the id of [fragment](crate::Fragment) which is used in this slot as value.
     pub slot_map: HashMap<u32, u32>,
+
+    /// A map with the id of [slot](crate::Slot) as key and the style of the fragment used in this slot as value.
+    pub styles: HashMap<u32, SlotStyle>,
+
+    /// The id of the target [palette](crate::Palette) for fragments in all slots.
+    ///
+    /// Can be overridden for each slot by [`SlotStyle::palette`].
+    pub palette: Option<u32>,
 }
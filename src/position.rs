@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{is_false, Point},
+    transform::Transform,
+};
+
+/// A place where a [slot](crate::Slot) is put inside the [doll](crate::Doll).
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Position {
+    /// The distance from the Y-axis
+    pub x: f32,
+    /// The distance from the X-axis
+    pub y: f32,
+
+    /// Overrides the [depth](crate::Slot::depth) of the slot at this position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<i32>,
+
+    /// Whether to mirror the fragment horizontally at this position.
+    ///
+    /// In non-constrainted mode, the fragment is mirrored around the anchor point of the slot, so its pivot is mirrored as well.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub flip_x: bool,
+
+    /// Whether to mirror the fragment vertically at this position.
+    ///
+    /// In non-constrainted mode, the fragment is mirrored around the anchor point of the slot, so its pivot is mirrored as well.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub flip_y: bool,
+
+    /// The transformation applied to the fragment at this position, around the anchor point of the slot.
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    pub transform: Transform,
+}
+
+impl From<Point> for Position {
+    fn from(point: Point) -> Self {
+        Self::new(point.x, point.y)
+    }
+}
+
+impl Position {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            x,
+            y,
+            depth: None,
+            flip_x: false,
+            flip_y: false,
+            transform: Transform::default(),
+        }
+    }
+
+    /// Returns the coordinate of this position.
+    pub fn point(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use anyhow::{anyhow, bail, Result};
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::{
+    asset::{Asset, AssetLoader},
+    decode::decode,
+    encode::encode_png,
+    factory::PaperdollFactory,
+    image::ImageData,
+    manifest::Manifest,
+};
+
+/// The path of the manifest in the archive.
+const FILE_NAME_MANIFEST: &str = "paperdoll.json";
+/// The directory of images in the archive.
+const DIR_IMAGES: &str = "images";
+
+/// Resolves paths into the images stored in an archive.
+struct ArchiveLoader {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl AssetLoader for ArchiveLoader {
+    fn load(&self, path: &str) -> Result<ImageData> {
+        let bytes = self
+            .files
+            .get(path)
+            .ok_or(anyhow!("Failed to find {} in the archive", path))?;
+
+        decode(bytes).map_err(|e| anyhow!("Failed to decode {}: {}", path, e))
+    }
+}
+
+/// Reads a factory from a `ppd` archive.
+pub(crate) fn read(reader: impl Read) -> Result<PaperdollFactory> {
+    let mut archive = Archive::new(reader);
+
+    let mut manifest = None;
+    let mut files = HashMap::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+
+        if path == FILE_NAME_MANIFEST {
+            manifest = Some(
+                serde_json::from_slice::<Manifest>(&data)
+                    .map_err(|e| anyhow!("Failed to parse {}: {}", FILE_NAME_MANIFEST, e))?,
+            );
+        } else {
+            files.insert(path, data);
+        }
+    }
+
+    let manifest = manifest.ok_or(anyhow!(
+        "Failed to find {} in the archive",
+        FILE_NAME_MANIFEST
+    ))?;
+
+    let mut factory = PaperdollFactory::from_manifest(manifest)?;
+
+    if let Some((asset, e)) = factory
+        .load_images_with(&ArchiveLoader { files })
+        .into_iter()
+        .next()
+    {
+        bail!("Failed to load image of {}: {}", asset, e);
+    }
+
+    Ok(factory)
+}
+
+/// Writes the factory as a `ppd` archive.
+///
+/// The output only depends on the content of the factory, so that writing the same project twice gives the same bytes.
+pub(crate) fn write(factory: &PaperdollFactory, writer: impl Write) -> Result<()> {
+    let mut manifest = factory.to_manifest();
+
+    let mut files = vec![];
+
+    let mut store = |asset: Asset, image: &ImageData, path: &str| -> Result<String> {
+        let image = factory
+            .resolve(image, path)
+            .map_err(|e| anyhow!("Failed to load image of {}: {}", asset, e))?;
+
+        if image.is_empty() {
+            if !path.is_empty() {
+                bail!(
+                    "Failed to load image of {}: {} resolves to an empty image",
+                    asset,
+                    path
+                );
+            }
+
+            return Ok(String::default());
+        }
+
+        let mut data = vec![];
+
+        encode_png(&image, &mut data)
+            .map_err(|e| anyhow!("Failed to encode image of {}: {}", asset, e))?;
+
+        let path = match asset {
+            Asset::Doll(id) => format!("{}/doll_{}.png", DIR_IMAGES, id),
+            Asset::Fragment(id) => format!("{}/fragment_{}.png", DIR_IMAGES, id),
+            Asset::Layer { fragment, index } => {
+                format!("{}/fragment_{}_layer_{}.png", DIR_IMAGES, fragment, index)
+            }
+        };
+
+        files.push((path.clone(), data));
+
+        Ok(path)
+    };
+
+    for doll in &mut manifest.dolls {
+        doll.path = store(Asset::Doll(doll.id()), &doll.image, &doll.path)?;
+    }
+
+    for fragment in &mut manifest.fragments {
+        let id = fragment.id();
+
+        fragment.path = store(Asset::Fragment(id), &fragment.image, &fragment.path)?;
+
+        for (index, layer) in fragment.layers.iter_mut().enumerate() {
+            layer.path = store(
+                Asset::Layer {
+                    fragment: id,
+                    index,
+                },
+                &layer.image,
+                &layer.path,
+            )?;
+        }
+    }
+
+    let mut builder = Builder::new(writer);
+
+    append(
+        &mut builder,
+        FILE_NAME_MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+
+    for (path, data) in files {
+        append(&mut builder, &path, &data)?;
+    }
+
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Appends a file to the archive, leaving out the time and the owner so that the output is reproducible.
+fn append(builder: &mut Builder<impl Write>, path: &str, data: &[u8]) -> Result<()> {
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_size(data.len() as u64);
+
+    builder.append_data(&mut header, path, data)?;
+
+    Ok(())
+}
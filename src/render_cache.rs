@@ -0,0 +1,299 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{image::ImageData, paperdoll::Paperdoll, render_options::RenderOptions, tint::Tint};
+
+/// A least recently used cache of rendered images, limited by the total size of pixels.
+#[derive(Debug, Default)]
+pub(crate) struct RenderCache {
+    /// The maximum size of all cached pixels in bytes. `0` disables the cache.
+    max_bytes: usize,
+    bytes: usize,
+    /// Increases on each access. Used to find the least recently used entry.
+    tick: u64,
+    entries: HashMap<u64, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    paperdoll: Paperdoll,
+    options: RenderOptions,
+    image: ImageData,
+    last_used: u64,
+}
+
+impl RenderCache {
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    pub fn get(&mut self, paperdoll: &Paperdoll, options: &RenderOptions) -> Option<ImageData> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        self.tick += 1;
+
+        let entry = self
+            .entries
+            .get_mut(&key(paperdoll, options))
+            .filter(|entry| entry.paperdoll == *paperdoll && entry.options == *options)?;
+
+        entry.last_used = self.tick;
+
+        Some(entry.image.clone())
+    }
+
+    pub fn insert(&mut self, paperdoll: &Paperdoll, options: &RenderOptions, image: &ImageData) {
+        let size = image.pixels.len();
+
+        if size > self.max_bytes {
+            return;
+        }
+
+        self.tick += 1;
+
+        let entry = Entry {
+            paperdoll: paperdoll.clone(),
+            options: options.clone(),
+            image: image.clone(),
+            last_used: self.tick,
+        };
+
+        if let Some(entry) = self.entries.insert(key(paperdoll, options), entry) {
+            self.bytes -= entry.image.pixels.len();
+        }
+
+        self.bytes += size;
+
+        self.shrink();
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+
+        self.shrink();
+    }
+
+    /// Removes the least recently used entries until the cache fits in the limit.
+    fn shrink(&mut self) {
+        while self.bytes > self.max_bytes {
+            let Some(key) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.image.pixels.len();
+            }
+        }
+    }
+}
+
+/// Hashes the paperdoll and the options regardless of the iteration order of maps.
+fn key(paperdoll: &Paperdoll, options: &RenderOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    paperdoll.doll.hash(&mut hasher);
+    paperdoll.palette.hash(&mut hasher);
+
+    let mut slot_map = paperdoll.slot_map.iter().collect::<Vec<_>>();
+    slot_map.sort();
+    slot_map.hash(&mut hasher);
+
+    let mut styles = paperdoll.styles.iter().collect::<Vec<_>>();
+    styles.sort_by_key(|(slot, _)| **slot);
+
+    for (slot, style) in styles {
+        slot.hash(&mut hasher);
+
+        match style.tint {
+            Some(Tint::Multiply(color)) => (0u8, color).hash(&mut hasher),
+            Some(Tint::Hsl {
+                hue,
+                saturation,
+                lightness,
+            }) => (
+                1u8,
+                hue.to_bits(),
+                saturation.to_bits(),
+                lightness.to_bits(),
+            )
+                .hash(&mut hasher),
+            None => 2u8.hash(&mut hasher),
+        }
+
+        style.palette.hash(&mut hasher);
+        style.opacity.map(f32::to_bits).hash(&mut hasher);
+    }
+
+    options.compositing.hash(&mut hasher);
+    options.scale.to_bits().hash(&mut hasher);
+    options.framing.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::render_options::{Compositing, Framing};
+
+    fn image(bytes: usize) -> ImageData {
+        ImageData {
+            width: bytes as u32 / 4,
+            height: 1,
+            pixels: vec![0; bytes].into(),
+            ..Default::default()
+        }
+    }
+
+    fn paperdoll(doll: u32) -> Paperdoll {
+        Paperdoll {
+            doll,
+            ..Default::default()
+        }
+    }
+
+    fn is_cached(cache: &mut RenderCache, doll: u32) -> bool {
+        cache
+            .get(&paperdoll(doll), &RenderOptions::default())
+            .is_some()
+    }
+
+    #[test]
+    fn keeps_within_the_byte_limit() {
+        let mut cache = RenderCache::default();
+        cache.set_max_bytes(100);
+
+        let options = RenderOptions::default();
+
+        for doll in 0..3 {
+            cache.insert(&paperdoll(doll), &options, &image(40));
+        }
+
+        assert_eq!(cache.bytes, 80);
+        assert_eq!(cache.entries.len(), 2);
+
+        // Larger than the whole cache.
+        cache.insert(&paperdoll(3), &options, &image(120));
+
+        assert!(!is_cached(&mut cache, 3));
+        assert_eq!(cache.bytes, 80);
+
+        // Replacing an entry doesn't count it twice.
+        cache.insert(&paperdoll(2), &options, &image(20));
+
+        assert_eq!(cache.bytes, 60);
+
+        cache.set_max_bytes(30);
+
+        assert_eq!(cache.bytes, 20);
+        assert!(is_cached(&mut cache, 2));
+
+        cache.set_max_bytes(0);
+
+        assert!(cache.entries.is_empty());
+        assert!(!is_cached(&mut cache, 2));
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_first() {
+        let mut cache = RenderCache::default();
+        cache.set_max_bytes(120);
+
+        let options = RenderOptions::default();
+
+        for doll in 0..3 {
+            cache.insert(&paperdoll(doll), &options, &image(40));
+        }
+
+        // The oldest entry is used again, so the second one goes first.
+        assert!(is_cached(&mut cache, 0));
+
+        cache.insert(&paperdoll(3), &options, &image(40));
+
+        assert!(!is_cached(&mut cache, 1));
+
+        cache.insert(&paperdoll(4), &options, &image(40));
+
+        assert!(!is_cached(&mut cache, 2));
+
+        for doll in [0, 3, 4] {
+            assert!(is_cached(&mut cache, doll), "{}", doll);
+        }
+    }
+
+    #[test]
+    fn tells_apart_styles_and_options() {
+        let mut cache = RenderCache::default();
+        cache.set_max_bytes(1000);
+
+        let options = RenderOptions::default();
+        let plain = paperdoll(0);
+
+        cache.insert(&plain, &options, &image(4));
+
+        let tinted = |tint| {
+            let mut paperdoll = plain.clone();
+            paperdoll.styles.entry(1).or_default().tint = Some(tint);
+            paperdoll
+        };
+
+        let hsl = |hue| Tint::Hsl {
+            hue,
+            saturation: 0.0,
+            lightness: 0.0,
+        };
+
+        let paperdolls = [
+            tinted(Tint::Multiply([255, 0, 0, 255])),
+            tinted(Tint::Multiply([0, 255, 0, 255])),
+            tinted(hsl(10.0)),
+            tinted(hsl(20.0)),
+        ];
+
+        for paperdoll in &paperdolls {
+            assert!(cache.get(paperdoll, &options).is_none(), "{:?}", paperdoll);
+
+            cache.insert(paperdoll, &options, &image(4));
+        }
+
+        for paperdoll in &paperdolls {
+            assert!(cache.get(paperdoll, &options).is_some(), "{:?}", paperdoll);
+        }
+
+        let variants = [
+            RenderOptions {
+                scale: 2.0,
+                ..Default::default()
+            },
+            RenderOptions {
+                compositing: Compositing::Linear,
+                ..Default::default()
+            },
+            RenderOptions {
+                framing: Framing::Crop,
+                ..Default::default()
+            },
+        ];
+
+        for options in &variants {
+            assert!(cache.get(&plain, options).is_none(), "{:?}", options);
+
+            cache.insert(&plain, options, &image(4));
+
+            assert!(cache.get(&plain, options).is_some(), "{:?}", options);
+        }
+
+        assert!(cache.get(&plain, &options).is_some());
+    }
+}
@@ -1,6 +1,15 @@
-use crate::{common::Point, image::ImageData};
+use crate::{
+    blend_mode::BlendMode,
+    clip::Clip,
+    common::{Point, Rect},
+    image::ImageData,
+    resample::Filter,
+    tint::Tint,
+    transform::{Affine, Transform},
+};
 
 /// An intermediate representation that describes the structure of a paper doll.
+#[derive(Clone, Debug)]
 pub struct RenderMaterial {
     /// The width of the paper doll in pixels.
     pub width: u32,
@@ -8,18 +17,247 @@ pub struct RenderMaterial {
     pub height: u32,
     /// The `RenderPiece` for the doll to be displayed, if any.
     pub doll: Option<RenderPiece>,
-    /// The `RenderPiece` for all slots in this doll.
+    /// The `RenderPiece` for all slots in this doll, sorted by depth.
     pub slots: Vec<RenderPiece>,
 }
 
+impl RenderMaterial {
+    /// Returns the smallest rectangle containing the pixels of all non-transparent textures, which may extend beyond the doll.
+    ///
+    /// Works like [`Self::bounds`], but transparent margins of the textures are left out.
+    /// Clipping is not taken into account, so the result may still be larger than what is actually drawn.
+    pub fn alpha_bounds(&self) -> Rect {
+        self.pieces()
+            .filter(|piece| piece.opacity > 0.0 && piece.blend_mode != BlendMode::Erase)
+            .fold(Rect::default(), |bounds, piece| {
+                bounds.union(&piece.alpha_bounds())
+            })
+    }
+
+    /// Returns the smallest rectangle containing the pixels of all textures, which may extend beyond the doll.
+    /// The top left corner of the doll is the origin.
+    pub fn bounds(&self) -> Rect {
+        self.pieces().fold(Rect::default(), |bounds, piece| {
+            bounds.union(&piece.bounds())
+        })
+    }
+
+    /// Returns the material for the given area of the paper doll, with the top left corner of the area as the origin.
+    ///
+    /// Pieces outside the area are left out.
+    pub(crate) fn crop(&self, rect: Rect) -> Self {
+        let shift = |piece: &RenderPiece| {
+            (!piece.bounds().intersect(&rect).is_empty()).then(|| piece.shifted(rect))
+        };
+
+        Self {
+            width: rect.width,
+            height: rect.height,
+            doll: self.doll.as_ref().and_then(shift),
+            slots: self.slots.iter().filter_map(shift).collect(),
+        }
+    }
+
+    fn pieces(&self) -> impl Iterator<Item = &RenderPiece> {
+        self.doll.iter().chain(self.slots.iter())
+    }
+}
+
 /// Describes a unit of work for rendering textures.
 /// Currently for dolls and fragments that needs to be displayed.
+#[derive(Clone, Debug, PartialEq)]
 pub struct RenderPiece {
     /// The id. The same as the id of the doll or the fragment.
     pub id: u32,
+    /// The index of the [layer](crate::Layer) in the fragment this texture comes from.
+    /// [`None`] if it's the image of the doll or the fragment itself.
+    pub layer: Option<usize>,
+    /// The id of the [slot](crate::Slot) this texture is placed in.
+    /// [`None`] if it's the image of the doll.
+    pub slot: Option<u32>,
     /// The top left position of this texture.
     /// The top left corner of the doll is the origin.
     pub position: Point,
-    // The image data of the texture.
+    /// The draw order of this texture.
+    /// Textures with a greater depth should be drawn on top of those with a smaller one.
+    pub depth: i32,
+    /// Whether this texture should be mirrored horizontally.
+    pub flip_x: bool,
+    /// Whether this texture should be mirrored vertically.
+    pub flip_y: bool,
+    /// The point which the transformation is applied around.
+    /// The top left corner of the doll is the origin.
+    pub origin: Point,
+    /// The transformation of this texture.
+    pub transform: Transform,
+    /// The filter used to sample this texture when it's transformed or placed at sub-pixel positions.
+    pub filter: Filter,
+    /// The way this texture is blended with pixels beneath it.
+    pub blend_mode: BlendMode,
+    /// The opacity of this texture, from `0.0` to `1.0`.
+    pub opacity: f32,
+    /// The source whose alpha restricts where this texture can be drawn, if any.
+    pub clip: Option<Clip>,
+    /// The color adjustment applied to this texture, if any.
+    pub tint: Option<Tint>,
+    /// The ids of the source and target [palettes](crate::Palette) if colors of this texture need to be swapped.
+    ///
+    /// The pixel data of the texture, if any, is already swapped.
+    /// Backends loading textures on their own should swap colors before filtering or resizing them.
+    pub palette_swap: Option<(u32, u32)>,
+    /// The image data of the texture.
     pub image: ImageData,
 }
+
+impl RenderPiece {
+    /// Returns the pixels of the doll this texture covers, ignoring its transparent margins.
+    /// The top left corner of the doll is the origin.
+    ///
+    /// The result may be slightly larger than the drawn pixels, as filtering spreads the edges of transformed textures.
+    pub fn alpha_bounds(&self) -> Rect {
+        let area = self.image.alpha_bounds();
+
+        if area.is_empty() {
+            return area;
+        }
+
+        // Extends the area by a texel to include the spread of the filter.
+        let area = if self.is_aligned() {
+            area
+        } else {
+            Rect::new(area.x - 1, area.y - 1, area.width + 2, area.height + 2)
+                .intersect(&Rect::new(0, 0, self.image.width, self.image.height))
+        };
+
+        self.map_bounds(area)
+    }
+
+    /// Returns the pixels of the doll this texture may cover, which is the smallest rectangle containing the transformed texture.
+    /// The top left corner of the doll is the origin.
+    pub fn bounds(&self) -> Rect {
+        if self.image.is_empty() {
+            return Rect::default();
+        }
+
+        self.map_bounds(Rect::new(0, 0, self.image.width, self.image.height))
+    }
+
+    /// Can this texture be copied onto the doll without filtering?
+    ///
+    /// That's the case if it's not rotated, placed at whole pixels, and scaled by whole numbers with nearest-neighbor sampling,
+    /// as such scaling just repeats each pixel.
+    pub(crate) fn is_aligned(&self) -> bool {
+        let [a, b, c, d, e, f] = self.matrix();
+
+        let whole = |v: f32| v.fract() == 0.0;
+        let (scale_x, scale_y) = (a.abs(), d.abs());
+
+        b == 0.0
+            && c == 0.0
+            && scale_x >= 1.0
+            && scale_y >= 1.0
+            && whole(scale_x)
+            && whole(scale_y)
+            && whole(e)
+            && whole(f)
+            && (self.filter == Filter::Nearest || (scale_x == 1.0 && scale_y == 1.0))
+    }
+
+    /// Returns the 2D affine matrix in the form of `[a, b, c, d, e, f]` which maps coordinates of this texture to those of the doll.
+    /// A point `(x, y)` in the texture ends up at `(a * x + c * y + e, b * x + d * y + f)` in the doll.
+    ///
+    /// Flipping, positioning, and the transformation are all included.
+    pub fn matrix(&self) -> [f32; 6] {
+        let mut flip = Affine::identity();
+
+        if self.flip_x {
+            flip = Affine::translate(self.image.width as f32, 0.0) * Affine::scale(-1.0, 1.0);
+        }
+
+        if self.flip_y {
+            flip =
+                Affine::translate(0.0, self.image.height as f32) * Affine::scale(1.0, -1.0) * flip;
+        }
+
+        let offset = self.position - self.origin;
+
+        let matrix = Affine::translate(self.origin.x, self.origin.y)
+            * self.transform.to_affine()
+            * Affine::translate(offset.x, offset.y)
+            * flip;
+
+        matrix.0
+    }
+
+    /// Scales the texture by the given factor, with the top left corner of the doll as the origin.
+    ///
+    /// The texture is sampled with nearest-neighbor if the factor is an integer, which keeps pixel art crisp.
+    /// Otherwise it keeps its own filter.
+    pub(crate) fn scale(&mut self, factor: f32) {
+        if factor == 1.0 {
+            return;
+        }
+
+        // The matrix of the texture becomes `S(factor) * T(origin) * transform * ...`,
+        // where the uniform scaling can be moved into the transformation.
+        self.position = self.position - self.origin + self.origin * factor;
+        self.origin = self.origin * factor;
+        self.transform.scale_x *= factor;
+        self.transform.scale_y *= factor;
+
+        if factor.fract() == 0.0 {
+            self.filter = Filter::Nearest;
+        }
+    }
+
+    /// Returns a copy of this texture with the top left corner of the given area as the origin.
+    pub(crate) fn shifted(&self, rect: Rect) -> Self {
+        let offset = Point::new(rect.x as f32, rect.y as f32);
+
+        Self {
+            position: self.position - offset,
+            origin: self.origin - offset,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the pixels of the doll covered by the given area of the texture.
+    fn map_bounds(&self, area: Rect) -> Rect {
+        let left = area.x as f32;
+        let top = area.y as f32;
+        let right = left + area.width as f32;
+        let bottom = top + area.height as f32;
+
+        let matrix = Affine(self.matrix());
+
+        let corners = [
+            Point::new(left, top),
+            Point::new(right, top),
+            Point::new(left, bottom),
+            Point::new(right, bottom),
+        ]
+        .map(|corner| matrix.apply(corner));
+
+        let (min_x, min_y, max_x, max_y) = corners.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(min_x, min_y, max_x, max_y), corner| {
+                (
+                    min_x.min(corner.x),
+                    min_y.min(corner.y),
+                    max_x.max(corner.x),
+                    max_y.max(corner.y),
+                )
+            },
+        );
+
+        let left = min_x.floor() as i32;
+        let top = min_y.floor() as i32;
+
+        Rect::new(
+            left,
+            top,
+            (max_x.ceil() as i32 - left) as u32,
+            (max_y.ceil() as i32 - top) as u32,
+        )
+    }
+}
@@ -0,0 +1,66 @@
+/// Options used when rendering a paper doll.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// How pixels are composited together.
+    pub compositing: Compositing,
+
+    /// The factor by which the output image is scaled. Defaults to `1.0`.
+    ///
+    /// Scaling is applied to each texture while compositing.
+    /// Integer factors use nearest-neighbor sampling, which keeps pixel art crisp.
+    /// Other factors use the [filter](crate::Slot::filter) of each slot.
+    pub scale: f32,
+
+    /// The area of the doll covered by the output image.
+    pub framing: Framing,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            compositing: Compositing::default(),
+            scale: 1.0,
+            framing: Framing::default(),
+        }
+    }
+}
+
+/// Ways to composite pixels when rendering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Compositing {
+    /// Blends straight alpha in sRGB space, with exact arithmetic.
+    ///
+    /// Each pixel is the correctly rounded result of the ideal formula,
+    /// without the precision lost by storing premultiplied colors in 8 bits.
+    #[default]
+    Straight,
+    /// Blends premultiplied alpha in sRGB space, rounding premultiplied colors to 8 bits like an 8 bits render target.
+    ///
+    /// With [`BlendMode::Normal`](crate::BlendMode::Normal), this matches a GPU blending 8 bits premultiplied textures
+    /// with `ONE, ONE_MINUS_SRC_ALPHA` into an 8 bits non-sRGB render target, bit for bit once the output is premultiplied.
+    /// This holds for textures drawn at integer positions without filtering, tint or opacity,
+    /// which the GPU would apply to premultiplied colors instead.
+    ///
+    /// Differs from [`Compositing::Straight`] mostly where alpha is low, as premultiplied colors lose precision there.
+    Premultiplied,
+    /// Blends in linear light.
+    ///
+    /// Close to a GPU blending premultiplied textures into an sRGB render target, which converts colors to linear light before blending.
+    /// This is the default for most game engines, eg. Bevy.
+    Linear,
+}
+
+/// Ways to decide the area of the doll covered by the rendered image.
+///
+/// Use [`PaperdollFactory::render_framed`](crate::PaperdollFactory::render_framed) to find out where the doll is placed in the image.
+/// Ignored when rendering into a buffer or with a [`Renderer`](crate::Renderer), which always cover the doll.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// Covers the doll. Textures extending beyond the doll are clipped.
+    #[default]
+    Doll,
+    /// Covers the doll, expanded to include all textures extending beyond it.
+    Expand,
+    /// Covers only the non-transparent pixels, which may extend beyond the doll.
+    Crop,
+}
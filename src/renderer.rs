@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::{
+    common::Rect,
+    compositor::Canvas,
+    factory::PaperdollFactory,
+    image::ImageData,
+    paperdoll::Paperdoll,
+    render_material::{RenderMaterial, RenderPiece},
+    render_options::RenderOptions,
+};
+
+/// A stateful renderer which keeps the last rendered image of a paperdoll.
+///
+/// When the paperdoll changes, only the area covered by the changed fragments is composited again.
+/// Useful for editors where a single slot changes at a time.
+///
+/// # Examples
+///
+/// ```
+/// use paperdoll::{PaperdollFactory, Renderer};
+///
+/// let factory = PaperdollFactory::default();
+///
+/// let paperdoll = factory.builder().build();
+///
+/// let mut renderer = Renderer::default();
+///
+/// // The first render composites the whole image.
+/// let dirty = renderer.render(&factory, &paperdoll).unwrap();
+///
+/// // Nothing changed since the last render.
+/// let dirty = renderer.render(&factory, &paperdoll).unwrap();
+/// assert!(dirty.is_none());
+///
+/// let image_data = renderer.image();
+/// ```
+#[derive(Debug, Default)]
+pub struct Renderer {
+    options: RenderOptions,
+    image: ImageData,
+    material: Option<RenderMaterial>,
+}
+
+impl Renderer {
+    /// Creates a renderer using the given options.
+    pub fn new(options: RenderOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the last rendered image.
+    pub fn image(&self) -> &ImageData {
+        &self.image
+    }
+
+    /// Forgets the last render, so that the next render composites the whole image again.
+    ///
+    /// Changes made to the factory (eg. new pixels of a fragment, or new colors of a palette) are not always detected.
+    /// Call this after modifying the factory.
+    pub fn invalidate(&mut self) {
+        self.material = None;
+    }
+
+    /// Returns the options used for rendering.
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    /// Renders the given paperdoll, compositing only the area which differs from the last render.
+    ///
+    /// Returns the area of the image which has been updated, or [`None`] if nothing changed.
+    /// The whole image is updated on the first render, or when the doll or the size of the image changes.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll is invalid. The last rendered image is kept in that case.
+    /// - Will return an error if memory runs out while compositing.
+    ///   The image may be partly updated in that case, and the next render composites the whole image again.
+    pub fn render(
+        &mut self,
+        factory: &PaperdollFactory,
+        paperdoll: &Paperdoll,
+    ) -> Result<Option<Rect>> {
+        let material = factory.analyse_with_options(paperdoll, &self.options)?;
+
+        let canvas = Rect::new(0, 0, material.width, material.height);
+
+        let dirty = match &self.material {
+            Some(last)
+                if last.width == material.width
+                    && last.height == material.height
+                    && last.doll == material.doll =>
+            {
+                dirty_rect(last, &material).map(|rect| rect.intersect(&canvas))
+            }
+            _ => Some(canvas),
+        };
+
+        let result = match dirty {
+            Some(rect) if rect == canvas => {
+                factory.compose_into(material.clone(), &self.options, &mut self.image)
+            }
+            Some(rect) if !rect.is_empty() => self.compose_rect(factory, &material, rect),
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            self.invalidate();
+
+            return Err(err);
+        }
+
+        self.material = Some(material);
+
+        Ok(dirty.filter(|rect| !rect.is_empty()))
+    }
+
+    /// Sets the options used for rendering.
+    /// The next render composites the whole image again.
+    pub fn set_options(&mut self, options: RenderOptions) {
+        self.options = options;
+
+        self.invalidate();
+    }
+
+    /// Composites the given area of the material, and replaces that area of the image with the result.
+    fn compose_rect(
+        &mut self,
+        factory: &PaperdollFactory,
+        material: &RenderMaterial,
+        rect: Rect,
+    ) -> Result<()> {
+        let stride = self.image.width as usize * 4;
+        let start = rect.y as usize * stride + rect.x as usize * 4;
+
+        let mut canvas = Canvas {
+            width: rect.width,
+            height: rect.height,
+            stride,
+            pixels: &mut Arc::make_mut(&mut self.image.pixels)[start..],
+        };
+
+        canvas.clear();
+
+        factory.compose_canvas(material.crop(rect), &self.options, &mut canvas)
+    }
+}
+
+/// Returns the area covered by the pieces which differ between two materials,
+/// or [`None`] if the materials are the same.
+///
+/// Returns the whole canvas if the same pieces are drawn in a different order.
+fn dirty_rect(last: &RenderMaterial, material: &RenderMaterial) -> Option<Rect> {
+    let mut matched = vec![false; material.slots.len()];
+    let mut last_matched_index = None;
+    let mut rect = None;
+
+    let mut mark = |piece: &RenderPiece| {
+        rect = Some(piece.bounds().union(&rect.unwrap_or_default()));
+    };
+
+    for piece in &last.slots {
+        let index = material
+            .slots
+            .iter()
+            .enumerate()
+            .position(|(index, other)| !matched[index] && other == piece);
+
+        match index {
+            Some(index) => {
+                if last_matched_index.is_some_and(|last_index| last_index > index) {
+                    return Some(Rect::new(0, 0, material.width, material.height));
+                }
+
+                matched[index] = true;
+                last_matched_index = Some(index);
+            }
+            None => mark(piece),
+        }
+    }
+
+    for (piece, matched) in material.slots.iter().zip(matched) {
+        if !matched {
+            mark(piece);
+        }
+    }
+
+    rect
+}
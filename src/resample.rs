@@ -0,0 +1,435 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::image::{ColorType, ImageData};
+
+/// Filters used when an image needs to be resized.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+pub enum Filter {
+    /// Nearest-neighbor sampling. Keeps hard edges, best for pixel art.
+    Nearest,
+    /// Bilinear interpolation.
+    #[default]
+    Bilinear,
+    /// Bicubic interpolation using the Catmull-Rom spline.
+    CatmullRom,
+    /// Lanczos resampling with a window of 3.
+    Lanczos3,
+}
+
+impl Filter {
+    fn support(&self) -> f32 {
+        match self {
+            Self::Nearest => 0.5,
+            Self::Bilinear => 1.0,
+            Self::CatmullRom => 2.0,
+            Self::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+
+        match self {
+            Self::Nearest => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::CatmullRom => {
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Lanczos3 => {
+                if x < f32::EPSILON {
+                    1.0
+                } else if x < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Resizes the image to `width` x `height` using the given filter.
+///
+/// Filtering happens on premultiplied alpha so that transparent pixels do not bleed their color into the result.
+pub(crate) fn resample(src: &ImageData, width: u32, height: u32, filter: Filter) -> ImageData {
+    if src.width == width && src.height == height {
+        return src.clone();
+    }
+
+    let mut image = ImageData {
+        width,
+        height,
+        color_type: ColorType::Rgba,
+        pixels: Arc::default(),
+    };
+
+    if !src.has_pixels() || src.is_empty() || width == 0 || height == 0 {
+        return image;
+    }
+
+    if filter == Filter::Nearest {
+        image.pixels = Arc::new(resample_nearest(src, width, height));
+
+        return image;
+    }
+
+    let src_width = src.width as usize;
+    let src_height = src.height as usize;
+    let width = width as usize;
+    let height = height as usize;
+
+    let premultiplied = src
+        .pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as f32 / 255.0;
+
+            [
+                pixel[0] as f32 * alpha,
+                pixel[1] as f32 * alpha,
+                pixel[2] as f32 * alpha,
+                pixel[3] as f32,
+            ]
+        })
+        .collect::<Vec<f32>>();
+
+    let weights_x = compute_weights(src_width, width, filter);
+    let weights_y = compute_weights(src_height, height, filter);
+
+    let mut horizontal = vec![0.0; width * src_height * 4];
+
+    for y in 0..src_height {
+        let src_row = &premultiplied[y * src_width * 4..(y + 1) * src_width * 4];
+        let dst_row = &mut horizontal[y * width * 4..(y + 1) * width * 4];
+
+        for (x, (start, weights)) in weights_x.iter().enumerate() {
+            for (i, weight) in weights.iter().enumerate() {
+                let cursor = (start + i) * 4;
+
+                for c in 0..4 {
+                    dst_row[x * 4 + c] += src_row[cursor + c] * weight;
+                }
+            }
+        }
+    }
+
+    let mut pixels = vec![0; width * height * 4];
+
+    for (y, (start, weights)) in weights_y.iter().enumerate() {
+        for x in 0..width {
+            let mut sum = [0.0; 4];
+
+            for (i, weight) in weights.iter().enumerate() {
+                let cursor = ((start + i) * width + x) * 4;
+
+                for c in 0..4 {
+                    sum[c] += horizontal[cursor + c] * weight;
+                }
+            }
+
+            let cursor = (y * width + x) * 4;
+            let alpha = sum[3].clamp(0.0, 255.0);
+
+            if alpha > 0.0 {
+                for c in 0..3 {
+                    pixels[cursor + c] = (sum[c] * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+
+            pixels[cursor + 3] = alpha.round() as u8;
+        }
+    }
+
+    image.pixels = Arc::new(pixels);
+
+    image
+}
+
+/// Samples the image at the given coordinate using the given filter.
+/// The top left corner of the image is the origin, pixel centers lie at half-integer coordinates.
+///
+/// `footprint` is the size of a destination pixel in pixels of the image on each axis.
+/// The filter is widened by it when the image is minified, so that every pixel of the image contributes.
+///
+/// Pixels outside the image are treated as transparent.
+/// Returns [`None`] if the sampled color is fully transparent.
+pub(crate) fn sample(
+    src: &ImageData,
+    u: f32,
+    v: f32,
+    filter: Filter,
+    footprint: (f32, f32),
+) -> Option<[u8; 4]> {
+    let width = src.width as isize;
+    let height = src.height as isize;
+
+    if filter == Filter::Nearest {
+        if u < 0.0 || v < 0.0 {
+            return None;
+        }
+
+        let (x, y) = (u as isize, v as isize);
+
+        if x >= width || y >= height {
+            return None;
+        }
+
+        let cursor = (y * width + x) as usize * 4;
+        let pixel = &src.pixels[cursor..cursor + 4];
+
+        return (pixel[3] != 0).then(|| [pixel[0], pixel[1], pixel[2], pixel[3]]);
+    }
+
+    let cu = u - 0.5;
+    let cv = v - 0.5;
+
+    // The edges of the image are antialiased as if it wasn't minified, so that they stay as sharp as the filter.
+    let coverage = coverage(cu, width, filter) * coverage(cv, height, filter);
+
+    if coverage == 0.0 {
+        return None;
+    }
+
+    // Never wider than the image, which bounds the work for extreme minifications.
+    let scale_x = footprint.0.clamp(1.0, src.width as f32);
+    let scale_y = footprint.1.clamp(1.0, src.height as f32);
+
+    let radius_x = (filter.support() * scale_x).ceil() as isize;
+    let radius_y = (filter.support() * scale_y).ceil() as isize;
+
+    let left = (cu.floor() as isize - radius_x + 1).max(0);
+    let top = (cv.floor() as isize - radius_y + 1).max(0);
+    let right = (cu.floor() as isize + radius_x + 1).min(width);
+    let bottom = (cv.floor() as isize + radius_y + 1).min(height);
+
+    let mut sum = [0.0; 4];
+    let mut weight_sum = 0.0;
+
+    for y in top..bottom {
+        let weight_y = filter.weight((y as f32 - cv) / scale_y);
+
+        for x in left..right {
+            let weight = weight_y * filter.weight((x as f32 - cu) / scale_x);
+
+            weight_sum += weight;
+
+            let cursor = (y * width + x) as usize * 4;
+            let pixel = &src.pixels[cursor..cursor + 4];
+
+            let alpha = pixel[3] as f32;
+
+            sum[0] += pixel[0] as f32 * alpha / 255.0 * weight;
+            sum[1] += pixel[1] as f32 * alpha / 255.0 * weight;
+            sum[2] += pixel[2] as f32 * alpha / 255.0 * weight;
+            sum[3] += alpha * weight;
+        }
+    }
+
+    if weight_sum == 0.0 {
+        return None;
+    }
+
+    // The opacity of the image around the coordinate, before its edges are taken into account.
+    let opacity = (sum[3] / weight_sum).clamp(0.0, 255.0);
+    let alpha = opacity * coverage.clamp(0.0, 1.0);
+
+    if alpha < 0.5 {
+        return None;
+    }
+
+    let unpremultiply = |c: f32| (c / weight_sum * 255.0 / opacity).round().clamp(0.0, 255.0) as u8;
+
+    Some([
+        unpremultiply(sum[0]),
+        unpremultiply(sum[1]),
+        unpremultiply(sum[2]),
+        alpha.round() as u8,
+    ])
+}
+
+/// Returns how much of the unscaled filter centered at the given pixel coordinate falls inside an axis of the given length.
+fn coverage(center: f32, len: isize, filter: Filter) -> f32 {
+    let radius = filter.support().ceil() as isize;
+    let first = center.floor() as isize - radius + 1;
+
+    let (inside, total) = (first..first + radius * 2).fold((0.0, 0.0), |(inside, total), i| {
+        let weight = filter.weight(i as f32 - center);
+
+        if (0..len).contains(&i) {
+            (inside + weight, total + weight)
+        } else {
+            (inside, total + weight)
+        }
+    });
+
+    if total == 0.0 {
+        0.0
+    } else {
+        inside / total
+    }
+}
+
+/// Computes the contributing source range and the normalized weights for each destination pixel on one axis.
+fn compute_weights(src_len: usize, dst_len: usize, filter: Filter) -> Vec<(usize, Vec<f32>)> {
+    let ratio = src_len as f32 / dst_len as f32;
+    let scale = ratio.max(1.0);
+    let support = filter.support() * scale;
+
+    (0..dst_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * ratio;
+
+            let start = ((center - support).floor().max(0.0) as usize).min(src_len - 1);
+            let end = ((center + support).ceil() as usize).clamp(start + 1, src_len);
+
+            let mut weights = (start..end)
+                .map(|j| filter.weight((j as f32 + 0.5 - center) / scale))
+                .collect::<Vec<f32>>();
+
+            let sum: f32 = weights.iter().sum();
+
+            if sum != 0.0 {
+                weights.iter_mut().for_each(|weight| *weight /= sum);
+            }
+
+            (start, weights)
+        })
+        .collect()
+}
+
+fn resample_nearest(src: &ImageData, width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for y in 0..height {
+        let sy = ((2 * y as u64 + 1) * src.height as u64 / (2 * height as u64)) as usize;
+
+        for x in 0..width {
+            let sx = ((2 * x as u64 + 1) * src.width as u64 / (2 * width as u64)) as usize;
+
+            let cursor = (sy * src.width as usize + sx) * 4;
+
+            pixels.extend_from_slice(&src.pixels[cursor..cursor + 4]);
+        }
+    }
+
+    pixels
+}
+
+fn sinc(x: f32) -> f32 {
+    let x = x * std::f32::consts::PI;
+
+    x.sin() / x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Bilinear,
+        Filter::CatmullRom,
+        Filter::Lanczos3,
+    ];
+
+    fn rgba(width: u32, height: u32, pixels: Vec<u8>) -> ImageData {
+        ImageData {
+            width,
+            height,
+            color_type: ColorType::Rgba,
+            pixels: pixels.into(),
+        }
+    }
+
+    fn gray(values: &[u8]) -> ImageData {
+        let pixels = values.iter().flat_map(|v| [*v, *v, *v, 255]).collect();
+
+        rgba(values.len() as u32, 1, pixels)
+    }
+
+    fn red_channel(image: &ImageData) -> Vec<u8> {
+        image.pixels.chunks_exact(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn resizes_to_the_given_size() {
+        let src = rgba(3, 2, (0..24).collect());
+
+        for filter in FILTERS {
+            for (width, height) in [(7, 5), (2, 1), (3, 4), (1, 1)] {
+                let image = resample(&src, width, height, filter);
+
+                assert_eq!((image.width, image.height), (width, height));
+                assert_eq!(image.pixels.len(), (width * height * 4) as usize);
+            }
+
+            assert!(resample(&src, 0, 4, filter).pixels.is_empty());
+            assert!(Arc::ptr_eq(
+                &resample(&src, 3, 2, filter).pixels,
+                &src.pixels
+            ));
+        }
+    }
+
+    #[test]
+    fn keeps_uniform_images_uniform() {
+        for filter in FILTERS {
+            for width in [1, 3, 8, 13] {
+                let image = resample(&gray(&[90; 5]), width, 2, filter);
+
+                assert!(
+                    image.pixels.chunks_exact(4).all(|p| p == [90, 90, 90, 255]),
+                    "{:?} to {}",
+                    filter,
+                    width
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uses_the_chosen_filter() {
+        let step = gray(&[0, 0, 255, 255]);
+
+        let results = FILTERS.map(|filter| red_channel(&resample(&step, 8, 1, filter)));
+
+        // Nearest-neighbor only repeats pixels, the others blend them along the edge.
+        assert_eq!(results[0], [0, 0, 0, 0, 255, 255, 255, 255]);
+        assert_eq!(results[1], [0, 0, 0, 64, 191, 255, 255, 255]);
+
+        for (i, result) in results.iter().enumerate() {
+            for other in &results[i + 1..] {
+                assert_ne!(result, other);
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_bleed() {
+        let src = rgba(2, 2, [[255, 0, 0, 255], [0, 255, 0, 0]].repeat(2).concat());
+
+        for filter in FILTERS {
+            for (width, height) in [(7, 3), (1, 1)] {
+                let image = resample(&src, width, height, filter);
+
+                for pixel in image.pixels.chunks_exact(4).filter(|p| p[3] != 0) {
+                    assert_eq!(pixel[..3], [255, 0, 0], "{:?}", filter);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use serde::{Deserialize, Serialize};
 
-use crate::common::{is_false, is_zero, Point};
+use crate::{
+    blend_mode::BlendMode,
+    clip::Clip,
+    common::{is_default, is_false, is_one, is_zero, Point},
+    position::Position,
+    resample::Filter,
+};
 
 /// Areas where the paper doll can have alternative styles.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -20,11 +26,19 @@ pub struct Slot {
     #[serde(default, skip_serializing_if = "is_false")]
     pub constrainted: bool,
 
+    /// The draw order of the slot.
+    /// Slots with a greater depth are drawn on top of those with a smaller one.
+    /// Slots with the same depth are drawn in the order they're listed in the [doll](crate::Doll).
+    ///
+    /// Can be overridden for each position by [`Position::depth`].
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub depth: i32,
+
     /// Slot's top left position in the [doll](crate::Doll).
     ///
     /// One slot can have multiple positions.
     #[serde(default = "default_positions", skip_serializing_if = "Vec::is_empty")]
-    pub positions: Vec<Point>,
+    pub positions: Vec<Position>,
 
     /// The width of the slot in pixels.
     #[serde(default, skip_serializing_if = "is_zero")]
@@ -41,6 +55,34 @@ pub struct Slot {
     #[serde(default, skip_serializing_if = "Point::is_zero")]
     pub anchor: Point,
 
+    /// The filter used to sample fragments in this slot.
+    ///
+    /// Used to resize fragments to the size of the slot in constrainted mode,
+    /// and to draw fragments which are rotated, scaled or placed at sub-pixel offsets by a [position](crate::Position).
+    ///
+    /// The default [`Filter::Bilinear`] smooths such fragments. Use [`Filter::Nearest`] to keep pixel art crisp.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filter: Filter,
+
+    /// The way fragments in this slot are blended with pixels beneath them.
+    ///
+    /// Can be overridden by [`Fragment::blend_mode`](crate::Fragment::blend_mode).
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub blend_mode: BlendMode,
+
+    /// The opacity of fragments in this slot, from `0.0` to `1.0`.
+    ///
+    /// Can be overridden in each [`Paperdoll`](crate::Paperdoll) by [`SlotStyle::opacity`](crate::SlotStyle::opacity).
+    #[serde(default = "default_opacity", skip_serializing_if = "is_one")]
+    pub opacity: f32,
+
+    /// Restricts fragments in this slot to the alpha of the doll or another slot in the same doll.
+    /// Pixels outside of the source are not drawn.
+    ///
+    /// Clipping to a doll without an image is an error, while clipping to an empty slot hides the fragments. See [`Clip`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip: Option<Clip>,
+
     /// A list of id of [fragments](crate::Fragment) those work as candidates in the slot.
     pub candidates: Vec<u32>,
 }
@@ -52,10 +94,15 @@ impl Slot {
             desc: String::default(),
             required: false,
             constrainted: false,
+            depth: 0,
             positions: default_positions(),
             width: 0,
             height: 0,
             anchor: Point::default(),
+            filter: Filter::default(),
+            blend_mode: BlendMode::default(),
+            opacity: default_opacity(),
+            clip: None,
             candidates: vec![],
         }
     }
@@ -65,6 +112,10 @@ impl Slot {
     }
 }
 
-fn default_positions() -> Vec<Point> {
-    vec![Point::default()]
+fn default_opacity() -> f32 {
+    1.0
+}
+
+fn default_positions() -> Vec<Position> {
+    vec![Position::default()]
 }
@@ -0,0 +1,15 @@
+use crate::tint::Tint;
+
+/// Adjusts how the fragment selected in a [slot](crate::Slot) is displayed in a [`Paperdoll`](crate::Paperdoll).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotStyle {
+    /// The color adjustment applied to the fragment, if any.
+    pub tint: Option<Tint>,
+
+    /// The id of the target [palette](crate::Palette) for the fragment, if any.
+    pub palette: Option<u32>,
+
+    /// The opacity of the fragment, from `0.0` to `1.0`.
+    /// Overrides [`Slot::opacity`](crate::Slot::opacity) if set.
+    pub opacity: Option<f32>,
+}
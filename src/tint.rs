@@ -0,0 +1,105 @@
+/// A color adjustment applied to a fragment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tint {
+    /// Multiplies each channel of the fragment by the given RGBA color.
+    Multiply([u8; 4]),
+    /// Shifts the color of the fragment in the HSL color space.
+    Hsl {
+        /// The hue shift in degrees.
+        hue: f32,
+        /// The saturation shift, from `-1.0` to `1.0`.
+        saturation: f32,
+        /// The lightness shift, from `-1.0` to `1.0`.
+        lightness: f32,
+    },
+}
+
+impl Tint {
+    /// Applies the tint to RGBA pixels in place.
+    pub(crate) fn apply(&self, pixels: &mut [u8]) {
+        match *self {
+            Self::Multiply(color) => {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    for (c, t) in pixel.iter_mut().zip(color) {
+                        *c = ((*c as u32 * t as u32 + 127) / 255) as u8;
+                    }
+                }
+            }
+            Self::Hsl {
+                hue,
+                saturation,
+                lightness,
+            } => {
+                for pixel in pixels.chunks_exact_mut(4) {
+                    if pixel[3] == 0 {
+                        continue;
+                    }
+
+                    let (h, s, l) = rgb_to_hsl(pixel[0], pixel[1], pixel[2]);
+
+                    let (r, g, b) = hsl_to_rgb(
+                        (h + hue).rem_euclid(360.0),
+                        (s + saturation).clamp(0.0, 1.0),
+                        (l + lightness).clamp(0.0, 1.0),
+                    );
+
+                    pixel[0] = r;
+                    pixel[1] = g;
+                    pixel[2] = b;
+                }
+            }
+        }
+    }
+}
+
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let r = r as f32 / 255.0;
+    let g = g as f32 / 255.0;
+    let b = b as f32 / 255.0;
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+
+    let l = (max + min) / 2.0;
+
+    if max == min {
+        return (0.0, 0.0, l);
+    }
+
+    let d = max - min;
+
+    let s = if l > 0.5 {
+        d / (2.0 - max - min)
+    } else {
+        d / (max + min)
+    };
+
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+
+    (h * 60.0, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (u8, u8, u8) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    let to_u8 = |v: f32| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    (to_u8(r), to_u8(g), to_u8(b))
+}
@@ -0,0 +1,127 @@
+use std::ops;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{is_one, Point};
+
+/// A 2D transformation applied to a fragment placed in a [slot](crate::Slot).
+///
+/// The fragment is scaled first, then rotated.
+/// Both happen around the anchor point of the slot.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Transform {
+    /// The rotation in degrees. Positive values rotate clockwise.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub rotation: f32,
+
+    /// The scale factor along the X-axis.
+    #[serde(skip_serializing_if = "is_one")]
+    pub scale_x: f32,
+
+    /// The scale factor along the Y-axis.
+    #[serde(skip_serializing_if = "is_one")]
+    pub scale_y: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            rotation: 0.0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn new(rotation: f32, scale_x: f32, scale_y: f32) -> Self {
+        Self {
+            rotation,
+            scale_x,
+            scale_y,
+        }
+    }
+
+    /// Does this transformation leave the fragment untouched?
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn to_affine(self) -> Affine {
+        Affine::rotate(self.rotation) * Affine::scale(self.scale_x, self.scale_y)
+    }
+}
+
+/// A 2D affine matrix in the form of `[a, b, c, d, e, f]`, which maps `(x, y)` to `(a * x + c * y + e, b * x + d * y + f)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Affine(pub [f32; 6]);
+
+impl ops::Mul<Self> for Affine {
+    type Output = Self;
+
+    /// Combines two matrices. `rhs` is applied first.
+    fn mul(self, rhs: Self) -> Self::Output {
+        let [a, b, c, d, e, f] = self.0;
+        let [ra, rb, rc, rd, re, rf] = rhs.0;
+
+        Self([
+            a * ra + c * rb,
+            b * ra + d * rb,
+            a * rc + c * rd,
+            b * rc + d * rd,
+            a * re + c * rf + e,
+            b * re + d * rf + f,
+        ])
+    }
+}
+
+impl Affine {
+    pub fn identity() -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+    }
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+
+    pub fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        Self([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+
+    pub fn apply(&self, point: Point) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+
+        Point::new(a * point.x + c * point.y + e, b * point.x + d * point.y + f)
+    }
+
+    /// Returns the inverse matrix, or [`None`] if the matrix is not invertible.
+    pub fn inverse(&self) -> Option<Self> {
+        let [a, b, c, d, e, f] = self.0;
+
+        let det = a * d - b * c;
+
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        Some(Self([
+            d / det,
+            -b / det,
+            -c / det,
+            a / det,
+            (c * f - d * e) / det,
+            (b * e - a * f) / det,
+        ]))
+    }
+}
+
+fn is_zero(v: &f32) -> bool {
+    *v == 0.0
+}
@@ -0,0 +1,111 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+use paperdoll::{AssetLoader, ColorType, ImageData, MemoryLoader, PaperdollFactory};
+
+/// Counts the images it loads.
+struct CountingLoader {
+    inner: MemoryLoader,
+    loads: Arc<AtomicUsize>,
+}
+
+impl AssetLoader for CountingLoader {
+    fn load(&self, path: &str) -> Result<ImageData> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+
+        self.inner.load(path)
+    }
+}
+
+fn pixel() -> ImageData {
+    ImageData {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Rgba,
+        pixels: vec![255, 0, 0, 255].into(),
+    }
+}
+
+#[test]
+fn images_are_loaded_lazily_and_once() {
+    let mut factory = PaperdollFactory::default();
+
+    let fragment_id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().path = "fragment.png".to_owned();
+
+    let slot_id = factory.add_slot().unwrap();
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 1;
+    doll.height = 1;
+    doll.path = "doll.png".to_owned();
+    doll.slots.push(slot_id);
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, fragment_id)
+        .build();
+
+    let mut inner = MemoryLoader::new();
+    inner.insert("doll.png", pixel());
+    inner.insert("fragment.png", pixel());
+
+    let loads = Arc::new(AtomicUsize::new(0));
+
+    factory.set_loader(CountingLoader {
+        inner,
+        loads: loads.clone(),
+    });
+
+    assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+    factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+    assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(image.pixels.as_slice(), [255, 0, 0, 255]);
+
+    factory.render_paperdoll(&paperdoll).unwrap();
+    factory.render_batch(&[paperdoll.clone(), paperdoll.clone()], &Default::default());
+
+    assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+    // The images stored in the doll and the fragment are left untouched.
+    assert!(factory.get_doll(0).unwrap().image.is_empty());
+    assert!(factory.get_fragment(fragment_id).unwrap().image.is_empty());
+
+    factory.unload_images();
+    factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(loads.load(Ordering::SeqCst), 4);
+}
+
+#[cfg(feature = "decode")]
+#[test]
+fn fs_loader_rejects_paths_outside_the_base_directory() {
+    let loader = paperdoll::FsLoader::new(std::env::temp_dir().join("paperdoll_assets"));
+
+    for path in ["../image.png", "/image.png", "a/../image.png"] {
+        let err = loader.load(path).unwrap_err();
+
+        assert!(err.to_string().contains("outside"), "{}: {}", path, err);
+    }
+
+    // Relative paths are read, and fail as the files don't exist.
+    for path in ["image.png", "./a/image.png"] {
+        let err = loader.load(path).unwrap_err();
+
+        assert!(
+            err.to_string().contains("Failed to read"),
+            "{}: {}",
+            path,
+            err
+        );
+    }
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "encode")]
+
+use std::fs;
+
+use paperdoll::{ColorType, ImageData, PaperdollFactory};
+
+fn image(width: u32, height: u32, color_type: ColorType, pixels: Vec<u8>) -> ImageData {
+    ImageData {
+        width,
+        height,
+        color_type,
+        pixels: pixels.into(),
+    }
+}
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 37 % 256) as u8).collect()
+}
+
+fn decode(bytes: &[u8]) -> image::DynamicImage {
+    image::load_from_memory_with_format(bytes, image::ImageFormat::Png).unwrap()
+}
+
+#[test]
+fn color_types_are_written_without_loss() {
+    let cases = [
+        (ColorType::Rgba, image::ColorType::Rgba8),
+        (ColorType::Rgb8, image::ColorType::Rgb8),
+        (ColorType::L8, image::ColorType::L8),
+        (ColorType::La8, image::ColorType::La8),
+        (ColorType::Rgba16, image::ColorType::Rgba16),
+    ];
+
+    for (color_type, expected) in cases {
+        let source = image(3, 2, color_type, pattern(6 * color_type.bytes_per_pixel()));
+
+        let mut bytes = vec![];
+
+        source.write_png(&mut bytes).unwrap();
+
+        let decoded = decode(&bytes);
+
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+        assert_eq!(decoded.color(), expected, "{:?}", color_type);
+        assert_eq!(
+            decoded.as_bytes(),
+            source.pixels.as_slice(),
+            "{:?}",
+            color_type
+        );
+    }
+}
+
+#[test]
+fn float_images_are_written_as_16_bits() {
+    let pixels = [0.0f32, 0.5, 1.0, 2.0, f32::NAN, -1.0, 0.25, 1.0]
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect();
+
+    let mut bytes = vec![];
+
+    image(2, 1, ColorType::Rgba32F, pixels)
+        .write_png(&mut bytes)
+        .unwrap();
+
+    let decoded = decode(&bytes);
+
+    let expected = [0u16, 32768, 65535, 65535, 0, 0, 16384, 65535]
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    assert_eq!(decoded.color(), image::ColorType::Rgba16);
+    assert_eq!(decoded.as_bytes(), expected.as_slice());
+}
+
+#[test]
+fn render_to_png_writes_the_rendered_image() {
+    let mut factory = PaperdollFactory::default();
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 4;
+    doll.height = 3;
+    doll.image = image(4, 3, ColorType::Rgba, pattern(4 * 3 * 4));
+
+    let paperdoll = factory.builder().doll(0).build();
+
+    let path = std::env::temp_dir().join(format!("paperdoll_render_{}.png", std::process::id()));
+
+    factory.render_to_png(&paperdoll, &path).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+
+    fs::remove_file(&path).unwrap();
+
+    let decoded = decode(&bytes);
+    let rendered = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!((decoded.width(), decoded.height()), (4, 3));
+    assert_eq!(decoded.color(), image::ColorType::Rgba8);
+    assert_eq!(decoded.as_bytes(), rendered.pixels.as_slice());
+}
@@ -0,0 +1,217 @@
+#![cfg(feature = "ppd")]
+
+use paperdoll::{ColorType, ImageData, Layer, MemoryLoader, PaperdollFactory};
+
+fn image(width: u32, height: u32, color_type: ColorType) -> ImageData {
+    let len = (width * height) as usize * color_type.bytes_per_pixel();
+
+    ImageData {
+        width,
+        height,
+        color_type,
+        pixels: (0..len)
+            .map(|i| (i * 37 % 256) as u8)
+            .collect::<Vec<_>>()
+            .into(),
+    }
+}
+
+/// A project using images of all color types stored without loss.
+fn project() -> PaperdollFactory {
+    let mut factory = PaperdollFactory::default();
+
+    factory.meta.name = "round trip".into();
+
+    let palette = factory.add_palette().unwrap();
+    factory.get_palette_mut(palette).unwrap().colors = vec![[1, 2, 3], [4, 5, 6]];
+
+    let color_types = [
+        ColorType::Rgba,
+        ColorType::Rgb8,
+        ColorType::L8,
+        ColorType::La8,
+    ];
+
+    let mut fragments = vec![];
+
+    for color_type in color_types {
+        let id = factory.add_fragment().unwrap();
+
+        let fragment = factory.get_fragment_mut(id).unwrap();
+        fragment.desc = format!("{:?}", color_type);
+        fragment.palette = Some(palette);
+        fragment.image = image(3, 2, color_type);
+
+        fragments.push(id);
+    }
+
+    let id = factory.add_fragment().unwrap();
+
+    let fragment = factory.get_fragment_mut(id).unwrap();
+    fragment.layers.push(Layer {
+        depth: 1,
+        image: image(2, 2, ColorType::Rgba16),
+        ..Default::default()
+    });
+
+    fragments.push(id);
+
+    let slot = factory.add_slot().unwrap();
+    factory.get_slot_mut(slot).unwrap().candidates = fragments;
+
+    let doll = *factory.dolls().next().unwrap().0;
+
+    let doll = factory.get_doll_mut(doll).unwrap();
+    doll.width = 4;
+    doll.height = 4;
+    doll.image = image(4, 4, ColorType::Rgba);
+    doll.slots.push(slot);
+
+    factory
+}
+
+fn write(factory: &PaperdollFactory) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    factory.write_ppd(&mut bytes).unwrap();
+
+    bytes
+}
+
+#[test]
+fn round_trip_keeps_images() {
+    let factory = project();
+
+    let loaded = PaperdollFactory::from_ppd(write(&factory).as_slice()).unwrap();
+
+    assert_eq!(loaded.meta.name, factory.meta.name);
+    assert_eq!(loaded.palettes().count(), 1);
+
+    for (id, doll) in factory.dolls() {
+        assert_eq!(loaded.get_doll(*id).unwrap().image, doll.image);
+    }
+
+    for (id, fragment) in factory.fragments() {
+        let other = loaded.get_fragment(*id).unwrap();
+
+        assert_eq!(other.desc, fragment.desc);
+        assert_eq!(other.palette, fragment.palette);
+        assert_eq!(other.image, fragment.image);
+        assert_eq!(other.layers.len(), fragment.layers.len());
+
+        for (other, layer) in other.layers.iter().zip(&fragment.layers) {
+            assert_eq!(other.depth, layer.depth);
+            assert_eq!(other.image, layer.image);
+        }
+    }
+
+    for (slot, fragment) in factory
+        .slots()
+        .flat_map(|(id, slot)| slot.candidates.iter().map(move |fragment| (*id, *fragment)))
+    {
+        let paperdoll = factory.builder().doll(0).set_slot(slot, fragment).build();
+
+        assert_eq!(
+            loaded.render_paperdoll(&paperdoll).unwrap(),
+            factory.render_paperdoll(&paperdoll).unwrap()
+        );
+    }
+}
+
+#[test]
+fn round_trip_is_byte_for_byte() {
+    let bytes = write(&project());
+
+    assert_eq!(write(&project()), bytes);
+
+    let loaded = PaperdollFactory::from_ppd(bytes.as_slice()).unwrap();
+
+    assert_eq!(write(&loaded), bytes);
+}
+
+#[test]
+fn float_images_are_stored_as_16_bits() {
+    let mut factory = PaperdollFactory::default();
+
+    let id = factory.add_fragment().unwrap();
+
+    let pixels = [0.0f32, 0.5, 1.0, 2.0]
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    factory.get_fragment_mut(id).unwrap().image = ImageData {
+        width: 1,
+        height: 1,
+        color_type: ColorType::Rgba32F,
+        pixels: pixels.into(),
+    };
+
+    let loaded = PaperdollFactory::from_ppd(write(&factory).as_slice()).unwrap();
+
+    let image = &loaded.get_fragment(id).unwrap().image;
+
+    let expected = [0u16, 32768, 65535, 65535]
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    assert_eq!(image.color_type, ColorType::Rgba16);
+    assert_eq!(image.pixels.as_slice(), expected.as_slice());
+}
+
+#[test]
+fn lazily_loaded_images_are_embedded() {
+    let mut factory = PaperdollFactory::default();
+
+    let id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(id).unwrap().path = "hat.png".into();
+
+    let mut loader = MemoryLoader::new();
+    loader.insert("hat.png", image(2, 1, ColorType::Rgba));
+
+    factory.set_loader(loader);
+
+    let loaded = PaperdollFactory::from_ppd(write(&factory).as_slice()).unwrap();
+
+    let fragment = loaded.get_fragment(id).unwrap();
+
+    assert_eq!(fragment.path, "images/fragment_0.png");
+    assert_eq!(fragment.image, image(2, 1, ColorType::Rgba));
+}
+
+#[test]
+fn unresolved_images_are_errors() {
+    let mut factory = PaperdollFactory::default();
+
+    let id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(id).unwrap().path = "missing.png".into();
+
+    factory.set_loader(MemoryLoader::new());
+
+    let err = factory.write_ppd(vec![]).unwrap_err();
+
+    assert!(err.to_string().contains("fragment with id 0"));
+}
+
+#[test]
+fn paths_without_images_are_errors() {
+    let mut factory = PaperdollFactory::default();
+
+    let id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(id).unwrap().path = "hat.png".into();
+
+    let err = factory.write_ppd(vec![]).unwrap_err();
+
+    assert!(err.to_string().contains("fragment with id 0"), "{}", err);
+    assert!(err.to_string().contains("hat.png"), "{}", err);
+}
+
+#[test]
+fn archives_without_manifest_are_errors() {
+    let Err(err) = PaperdollFactory::from_ppd([0u8; 1024].as_slice()) else {
+        panic!("expected an error");
+    };
+
+    assert!(err.to_string().contains("paperdoll.json"));
+}
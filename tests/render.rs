@@ -0,0 +1,1106 @@
+use std::sync::Arc;
+
+use paperdoll::{
+    BlendMode, Clip, ColorType, Filter, Framing, ImageData, Layer, MemoryLoader, Paperdoll,
+    PaperdollFactory, Point, Position, Rect, RenderOptions, Renderer, Slot, Tint, Transform,
+};
+
+fn rgba(width: u32, height: u32, pixels: Vec<u8>) -> ImageData {
+    ImageData {
+        width,
+        height,
+        color_type: ColorType::Rgba,
+        pixels: pixels.into(),
+    }
+}
+
+/// Adds a slot and a fragment with the given image, returning the id of both.
+fn add_filled_slot(
+    factory: &mut PaperdollFactory,
+    fragment: ImageData,
+    configure: impl FnOnce(&mut Slot),
+) -> (u32, u32) {
+    let fragment_id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().image = fragment;
+
+    let slot_id = factory.add_slot().unwrap();
+    configure(factory.get_slot_mut(slot_id).unwrap());
+
+    (slot_id, fragment_id)
+}
+
+/// A transparent doll with one slot holding the given fragment.
+fn single_slot_doll(
+    width: u32,
+    height: u32,
+    fragment: ImageData,
+    configure: impl FnOnce(&mut Slot),
+) -> (PaperdollFactory, Paperdoll) {
+    let mut factory = PaperdollFactory::default();
+
+    let (slot_id, fragment_id) = add_filled_slot(&mut factory, fragment, configure);
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = width;
+    doll.height = height;
+    doll.slots.push(slot_id);
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, fragment_id)
+        .build();
+
+    (factory, paperdoll)
+}
+
+fn pixel(image: &ImageData, x: u32, y: u32) -> [u8; 4] {
+    let cursor = (y * image.width + x) as usize * 4;
+
+    image.pixels[cursor..cursor + 4].try_into().unwrap()
+}
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+const BLUE: [u8; 4] = [0, 0, 255, 255];
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+#[test]
+fn palette_swap_leaves_no_fringe_when_resampled() {
+    let mut factory = PaperdollFactory::default();
+
+    let from = factory.add_palette().unwrap();
+    factory.get_palette_mut(from).unwrap().colors = vec![[255, 0, 0], [0, 0, 255]];
+
+    let to = factory.add_palette().unwrap();
+    factory.get_palette_mut(to).unwrap().colors = vec![[0, 255, 0], [255, 255, 0]];
+
+    let fragment_id = factory.add_fragment().unwrap();
+
+    let fragment = factory.get_fragment_mut(fragment_id).unwrap();
+    fragment.palette = Some(from);
+    fragment.image = rgba(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]);
+
+    let slot_id = factory.add_slot().unwrap();
+
+    // Bilinear filtering mixes the two colors of the fragment.
+    let slot = factory.get_slot_mut(slot_id).unwrap();
+    slot.constrainted = true;
+    slot.width = 8;
+    slot.height = 1;
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 8;
+    doll.height = 1;
+    doll.slots.push(slot_id);
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, fragment_id)
+        .palette(to)
+        .build();
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    // Mixes of green and yellow only.
+    for pixel in image.pixels.chunks_exact(4) {
+        assert_eq!(pixel[1], 255, "{:?}", pixel);
+        assert_eq!(pixel[2], 0, "{:?}", pixel);
+    }
+}
+
+/// A doll of the given size with a constrainted slot covering it, filled with a 3x3 fragment.
+fn constrainted_doll(size: u32) -> (PaperdollFactory, Paperdoll) {
+    let mut factory = PaperdollFactory::default();
+
+    let pixels = (0..9u8)
+        .flat_map(|i| [i * 28, 255 - i * 28, i * 7, 255])
+        .collect();
+
+    let fragment_id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().image = rgba(3, 3, pixels);
+
+    let slot_id = factory.add_slot().unwrap();
+
+    let slot = factory.get_slot_mut(slot_id).unwrap();
+    slot.constrainted = true;
+    slot.width = size;
+    slot.height = size;
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = size;
+    doll.height = size;
+    doll.slots.push(slot_id);
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, fragment_id)
+        .build();
+
+    (factory, paperdoll)
+}
+
+#[test]
+fn constrainted_slots_are_resampled_once_when_scaled() {
+    let (factory, paperdoll) = constrainted_doll(4);
+
+    let options = RenderOptions {
+        scale: 1.5,
+        ..Default::default()
+    };
+
+    let scaled = factory.render_with_options(&paperdoll, &options).unwrap();
+
+    // The fragment resampled straight to 6x6 pixels.
+    let (factory, paperdoll) = constrainted_doll(6);
+
+    let expected = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!((scaled.width, scaled.height), (6, 6));
+    assert_eq!(scaled.pixels, expected.pixels);
+}
+
+#[test]
+fn clips_to_slots_outside_the_doll_are_errors() {
+    let mut factory = PaperdollFactory::default();
+
+    let fragment_id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().image = rgba(1, 1, vec![0, 0, 0, 255]);
+
+    let outside = factory.add_slot().unwrap();
+    let slot_id = factory.add_slot().unwrap();
+
+    factory.get_slot_mut(slot_id).unwrap().clip = Some(Clip::Slot(outside));
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 1;
+    doll.height = 1;
+    doll.slots.push(slot_id);
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, fragment_id)
+        .build();
+
+    let err = factory.render_paperdoll(&paperdoll).unwrap_err();
+
+    assert!(err.to_string().contains("not in doll"), "{}", err);
+}
+
+#[test]
+fn clips_to_dolls_without_images_are_errors() {
+    let (factory, paperdoll) = single_slot_doll(1, 1, rgba(1, 1, RED.to_vec()), |slot| {
+        slot.clip = Some(Clip::Doll);
+    });
+
+    let err = factory.render_paperdoll(&paperdoll).unwrap_err();
+
+    assert!(err.to_string().contains("has no image"), "{}", err);
+}
+
+#[test]
+fn clips_limit_fragments_to_the_alpha_of_the_doll() {
+    let (mut factory, paperdoll) =
+        single_slot_doll(3, 1, rgba(3, 1, [BLUE, BLUE, BLUE].concat()), |slot| {
+            slot.clip = Some(Clip::Doll);
+        });
+
+    factory.get_doll_mut(0).unwrap().image = rgba(3, 1, [RED, CLEAR, RED].concat());
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(image.pixels.as_slice(), [BLUE, CLEAR, BLUE].concat());
+}
+
+/// A transparent doll of 3x1 pixels with blue fragments clipped to a slot holding the given mask, if any.
+fn clipped_slot_doll(mask: Option<ImageData>) -> (PaperdollFactory, Paperdoll) {
+    let mut factory = PaperdollFactory::default();
+
+    let fragment_id = factory.add_fragment().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().image = rgba(3, 1, [BLUE, BLUE, BLUE].concat());
+
+    let mask_id = mask.map(|mask| {
+        let mask_id = factory.add_fragment().unwrap();
+        factory.get_fragment_mut(mask_id).unwrap().image = mask;
+
+        mask_id
+    });
+
+    let mask_slot = factory.add_slot().unwrap();
+    let clipped_slot = factory.add_slot().unwrap();
+
+    let slot = factory.get_slot_mut(clipped_slot).unwrap();
+    slot.depth = 1;
+    slot.clip = Some(Clip::Slot(mask_slot));
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 3;
+    doll.height = 1;
+    doll.slots = vec![mask_slot, clipped_slot];
+
+    let builder = factory
+        .builder()
+        .doll(0)
+        .set_slot(clipped_slot, fragment_id);
+
+    let paperdoll = match mask_id {
+        Some(mask_id) => builder.set_slot(mask_slot, mask_id),
+        None => builder,
+    }
+    .build();
+
+    (factory, paperdoll)
+}
+
+#[test]
+fn clips_limit_fragments_to_the_alpha_of_the_slot() {
+    let mask = rgba(3, 1, [RED, [255, 0, 0, 128], CLEAR].concat());
+
+    let (factory, paperdoll) = clipped_slot_doll(Some(mask));
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    // Half of the blue is drawn over the translucent red.
+    assert_eq!(pixel(&image, 0, 0), BLUE);
+    assert_eq!(pixel(&image, 1, 0), [85, 0, 170, 192]);
+    assert_eq!(pixel(&image, 2, 0), CLEAR);
+}
+
+#[test]
+fn clips_to_empty_slots_hide_fragments() {
+    let (factory, paperdoll) = clipped_slot_doll(None);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert!(image.pixels.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn too_large_scales_are_errors() {
+    let mut factory = PaperdollFactory::default();
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 100;
+    doll.height = 100;
+
+    let paperdoll = factory.builder().doll(0).build();
+
+    // Overflows the size of the image, its byte length, and f32.
+    for scale in [1e8, 3e7, f32::MAX] {
+        let options = RenderOptions {
+            scale,
+            ..Default::default()
+        };
+
+        let err = factory
+            .render_with_options(&paperdoll, &options)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("too large"), "{}", err);
+    }
+}
+
+/// A doll of 4x2 opaque pixels, each with its coordinate as red and green.
+fn coordinates_doll() -> (PaperdollFactory, Paperdoll) {
+    let mut factory = PaperdollFactory::default();
+
+    let pixels = (0..2)
+        .flat_map(|y| (0..4).flat_map(move |x| [x, y, 7, 255]))
+        .collect();
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 4;
+    doll.height = 2;
+    doll.image = rgba(4, 2, pixels);
+
+    let paperdoll = factory.builder().doll(0).build();
+
+    (factory, paperdoll)
+}
+
+#[test]
+fn render_into_writes_rows_at_the_offset() {
+    let (factory, paperdoll) = coordinates_doll();
+
+    // 6x4 pixels with 2 extra bytes at the end of each row.
+    let stride = 6 * 4 + 2;
+    let mut buffer = vec![0xaa; stride * 4];
+
+    let area = factory
+        .render_into(
+            &paperdoll,
+            &RenderOptions::default(),
+            &mut buffer,
+            stride,
+            (1, 1),
+            None,
+        )
+        .unwrap();
+
+    assert_eq!(area, Rect::new(0, 0, 4, 2));
+
+    for (i, byte) in buffer.iter().enumerate() {
+        let (x, y, c) = (i % stride / 4, i / stride, i % stride % 4);
+
+        let expected = if (1..5).contains(&x) && (1..3).contains(&y) {
+            [x as u8 - 1, y as u8 - 1, 7, 255][c]
+        } else {
+            0xaa
+        };
+
+        assert_eq!(*byte, expected, "byte {} of pixel ({}, {})", c, x, y);
+    }
+}
+
+#[test]
+fn render_into_writes_only_the_clip() {
+    let (factory, paperdoll) = coordinates_doll();
+
+    let stride = 4 * 4;
+    let mut buffer = vec![0xaa; stride * 2];
+
+    // Partly outside the doll.
+    let area = factory
+        .render_into(
+            &paperdoll,
+            &RenderOptions::default(),
+            &mut buffer,
+            stride,
+            (0, 0),
+            Some(Rect::new(2, 1, 10, 10)),
+        )
+        .unwrap();
+
+    assert_eq!(area, Rect::new(2, 1, 2, 1));
+
+    // The clip is placed at the offset, the rest of the rows is untouched.
+    assert_eq!(buffer[..8], [2, 1, 7, 255, 3, 1, 7, 255]);
+    assert!(buffer[8..].iter().all(|byte| *byte == 0xaa));
+
+    for clip in [
+        Rect::new(4, 0, 1, 1),
+        Rect::new(i32::MAX, 0, 10, 10),
+        Rect::new(-10, 0, 10, 10),
+    ] {
+        let area = factory
+            .render_into(
+                &paperdoll,
+                &RenderOptions::default(),
+                &mut buffer,
+                stride,
+                (0, 0),
+                Some(clip),
+            )
+            .unwrap();
+
+        assert!(area.is_empty(), "{:?}", clip);
+    }
+
+    assert!(buffer[8..].iter().all(|byte| *byte == 0xaa));
+}
+
+#[test]
+fn render_into_rejects_areas_outside_the_buffer() {
+    let (factory, paperdoll) = coordinates_doll();
+
+    let options = RenderOptions::default();
+    let mut buffer = vec![0xaa; 64];
+
+    for (stride, offset) in [
+        (12, (0, 0)),
+        (16, (1, 0)),
+        (16, (0, 3)),
+        (usize::MAX, (0, 1)),
+        (usize::MAX, (u32::MAX, u32::MAX)),
+        (16, (u32::MAX, 0)),
+    ] {
+        let result = factory.render_into(&paperdoll, &options, &mut buffer, stride, offset, None);
+
+        assert!(result.is_err(), "stride {} at {:?}", stride, offset);
+    }
+
+    assert!(buffer.iter().all(|byte| *byte == 0xaa));
+}
+
+#[test]
+fn rotation_turns_fragments_clockwise_around_the_anchor() {
+    let (factory, paperdoll) = single_slot_doll(4, 4, rgba(2, 1, [RED, GREEN].concat()), |slot| {
+        slot.filter = Filter::Nearest;
+        slot.positions[0].x = 2.0;
+        slot.positions[0].y = 2.0;
+        slot.positions[0].transform = Transform::new(90.0, 1.0, 1.0);
+    });
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let expected = match (x, y) {
+                (1, 2) => RED,
+                (1, 3) => GREEN,
+                _ => CLEAR,
+            };
+
+            assert_eq!(pixel(&image, x, y), expected, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn non_uniform_scales_stretch_each_axis() {
+    let pixels = [RED, GREEN, BLUE, WHITE].concat();
+
+    let (factory, paperdoll) = single_slot_doll(4, 6, rgba(2, 2, pixels), |slot| {
+        slot.filter = Filter::Nearest;
+        slot.positions[0].transform = Transform::new(0.0, 2.0, 3.0);
+    });
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    for y in 0..6 {
+        for x in 0..4 {
+            let expected = [RED, GREEN, BLUE, WHITE][(y / 3 * 2 + x / 2) as usize];
+
+            assert_eq!(pixel(&image, x, y), expected, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn minified_fragments_are_filtered_by_all_their_pixels() {
+    // The outer rows fall outside the kernel unless it is widened.
+    let pixels = [RED, BLUE, BLUE, RED].concat();
+
+    let (factory, paperdoll) = single_slot_doll(1, 1, rgba(1, 4, pixels), |slot| {
+        slot.positions[0].transform = Transform::new(0.0, 1.0, 0.25);
+    });
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(pixel(&image, 0, 0), [106, 0, 149, 255]);
+}
+
+#[test]
+fn sub_pixel_offsets_are_filtered() {
+    let (factory, paperdoll) = single_slot_doll(3, 1, rgba(1, 1, WHITE.to_vec()), |slot| {
+        slot.positions[0].x = 1.5;
+    });
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    // Half of the fragment covers each of the two pixels.
+    assert_eq!(pixel(&image, 0, 0), CLEAR);
+    assert_eq!(pixel(&image, 1, 0), [255, 255, 255, 128]);
+    assert_eq!(pixel(&image, 2, 0), [255, 255, 255, 128]);
+}
+
+#[test]
+fn piece_matrices_include_pivot_flip_and_transform() {
+    let (mut factory, paperdoll) =
+        single_slot_doll(8, 8, rgba(2, 1, [RED, GREEN].concat()), |slot| {
+            slot.filter = Filter::Nearest;
+            slot.anchor = Point::new(1.0, 1.0);
+            slot.positions[0].x = 2.0;
+            slot.positions[0].y = 3.0;
+            slot.positions[0].flip_x = true;
+            slot.positions[0].transform = Transform::new(0.0, 2.0, 2.0);
+        });
+
+    let fragment_id = paperdoll.slot_map.values().next().copied().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().pivot = Point::new(1.0, 0.0);
+
+    let material = factory.analyse_paperdoll(&paperdoll, false).unwrap();
+
+    // Mirrored and doubled, with the pivot of the fragment on the anchor at (3, 4).
+    assert_eq!(material.slots[0].matrix(), [-2.0, 0.0, 0.0, 2.0, 5.0, 4.0]);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(pixel(&image, 1, 4), GREEN);
+    assert_eq!(pixel(&image, 2, 5), GREEN);
+    assert_eq!(pixel(&image, 3, 4), RED);
+    assert_eq!(pixel(&image, 4, 5), RED);
+    assert_eq!(pixel(&image, 5, 4), CLEAR);
+}
+
+#[test]
+fn slots_are_drawn_by_depth_around_the_doll() {
+    let mut factory = PaperdollFactory::default();
+
+    // Drawn over the doll, and under everything at its second position.
+    let (front, red) = add_filled_slot(&mut factory, rgba(1, 1, RED.to_vec()), |slot| {
+        slot.depth = 2;
+        slot.positions.push(Position {
+            depth: Some(-2),
+            ..Position::new(2.0, 0.0)
+        });
+    });
+
+    let (back, blue) = add_filled_slot(&mut factory, rgba(3, 1, [BLUE; 3].concat()), |slot| {
+        slot.depth = -1;
+    });
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 3;
+    doll.height = 1;
+    doll.image = rgba(3, 1, [GREEN, GREEN, CLEAR].concat());
+    doll.slots = vec![front, back];
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(front, red)
+        .set_slot(back, blue)
+        .build();
+
+    let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+    let depths = material
+        .slots
+        .iter()
+        .map(|piece| (piece.slot.unwrap(), piece.depth))
+        .collect::<Vec<_>>();
+
+    assert_eq!(depths, [(front, -2), (back, -1), (front, 2)]);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(image.pixels.as_slice(), [RED, GREEN, BLUE].concat());
+}
+
+#[test]
+fn layers_are_drawn_at_their_own_depth_and_pivot() {
+    let mut factory = PaperdollFactory::default();
+
+    let (head, blue) = add_filled_slot(&mut factory, rgba(2, 1, [BLUE; 2].concat()), |slot| {
+        slot.depth = -1;
+    });
+
+    let (hat, red) = add_filled_slot(&mut factory, rgba(1, 1, RED.to_vec()), |_| {});
+
+    // Behind the head, one pixel to the right of the fragment.
+    factory.get_fragment_mut(red).unwrap().layers.push(Layer {
+        pivot: Point::new(-1.0, 0.0),
+        depth: -2,
+        image: rgba(2, 1, [GREEN; 2].concat()),
+        ..Default::default()
+    });
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 3;
+    doll.height = 1;
+    doll.slots = vec![hat, head];
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(hat, red)
+        .set_slot(head, blue)
+        .build();
+
+    let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+    let pieces = material
+        .slots
+        .iter()
+        .map(|piece| (piece.slot.unwrap(), piece.layer, piece.depth))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        pieces,
+        [(hat, Some(0), -2), (head, None, -1), (hat, None, 0)]
+    );
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(image.pixels.as_slice(), [RED, BLUE, GREEN].concat());
+}
+
+#[test]
+fn flips_mirror_fragments_around_the_anchor() {
+    let pixels = [RED, GREEN, BLUE, WHITE].concat();
+
+    let (factory, paperdoll) = single_slot_doll(4, 4, rgba(2, 2, pixels), |slot| {
+        slot.positions = [(false, false), (true, false), (false, true), (true, true)]
+            .map(|(flip_x, flip_y)| Position {
+                flip_x,
+                flip_y,
+                ..Position::new(2.0, 2.0)
+            })
+            .to_vec();
+    });
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    // Each position lands in its own quarter around the anchor at (2, 2).
+    let expected = [
+        [WHITE, BLUE, BLUE, WHITE],
+        [GREEN, RED, RED, GREEN],
+        [GREEN, RED, RED, GREEN],
+        [WHITE, BLUE, BLUE, WHITE],
+    ]
+    .concat()
+    .concat();
+
+    assert_eq!(image.pixels.as_slice(), expected);
+}
+
+#[test]
+fn flips_mirror_the_pivot() {
+    let pixels = [RED, GREEN, BLUE].concat();
+
+    let (mut factory, paperdoll) = single_slot_doll(5, 1, rgba(3, 1, pixels), |slot| {
+        slot.anchor = Point::new(1.0, 0.0);
+        slot.positions = vec![
+            Position::new(1.0, 0.0),
+            Position {
+                flip_x: true,
+                ..Position::new(1.0, 0.0)
+            },
+        ];
+    });
+
+    let fragment_id = paperdoll.slot_map.values().next().copied().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().pivot = Point::new(1.0, 0.0);
+
+    let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+    // The pivot of the fragment is on the anchor at x = 2 either way.
+    let positions = material
+        .slots
+        .iter()
+        .map(|piece| piece.position.x)
+        .collect::<Vec<_>>();
+
+    assert_eq!(positions, [1.0, 0.0]);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    // Mirrored around x = 2, and drawn over the first position where they overlap.
+    assert_eq!(
+        image.pixels.as_slice(),
+        [BLUE, GREEN, RED, BLUE, CLEAR].concat()
+    );
+}
+
+#[test]
+fn tints_adjust_the_colors_of_fragments() {
+    let cases = [
+        // Rounded per channel, including alpha.
+        (
+            [200, 100, 50, 255],
+            Tint::Multiply([128, 255, 0, 128]),
+            [100, 100, 0, 128],
+        ),
+        (
+            [255, 0, 0, 128],
+            Tint::Hsl {
+                hue: 120.0,
+                saturation: 0.0,
+                lightness: 0.0,
+            },
+            [0, 255, 0, 128],
+        ),
+        (
+            RED,
+            Tint::Hsl {
+                hue: 0.0,
+                saturation: -1.0,
+                lightness: 0.0,
+            },
+            [128, 128, 128, 255],
+        ),
+        (
+            RED,
+            Tint::Hsl {
+                hue: 0.0,
+                saturation: 0.0,
+                lightness: 0.5,
+            },
+            WHITE,
+        ),
+    ];
+
+    for (color, tint, expected) in cases {
+        let (factory, paperdoll) = single_slot_doll(1, 1, rgba(1, 1, color.to_vec()), |_| {});
+
+        let (slot_id, fragment_id) = paperdoll.slot_map.iter().next().unwrap();
+
+        let paperdoll = factory
+            .builder()
+            .doll(0)
+            .set_slot(*slot_id, *fragment_id)
+            .tint(*slot_id, tint)
+            .build();
+
+        let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+        assert_eq!(material.slots[0].tint, Some(tint));
+
+        let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+        assert_eq!(pixel(&image, 0, 0), expected, "{:?} of {:?}", tint, color);
+    }
+}
+
+#[test]
+fn blend_modes_mix_fragments_with_the_doll() {
+    let cases = [
+        (BlendMode::Normal, [100, 200, 150, 255]),
+        (BlendMode::Multiply, [78, 78, 29, 255]),
+        (BlendMode::Screen, [222, 222, 171, 255]),
+        (BlendMode::Overlay, [188, 157, 59, 255]),
+        (BlendMode::Add, [255, 255, 200, 255]),
+    ];
+
+    for (mode, expected) in cases {
+        let (mut factory, paperdoll) =
+            single_slot_doll(1, 1, rgba(1, 1, vec![100, 200, 150, 255]), |slot| {
+                slot.blend_mode = mode;
+            });
+
+        factory.get_doll_mut(0).unwrap().image = rgba(1, 1, vec![200, 100, 50, 255]);
+
+        let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+        assert_eq!(pixel(&image, 0, 0), expected, "{:?}", mode);
+    }
+}
+
+#[test]
+fn erase_removes_the_alpha_of_the_fragment() {
+    let (mut factory, paperdoll) = single_slot_doll(1, 1, rgba(1, 1, vec![0, 0, 0, 64]), |slot| {
+        slot.blend_mode = BlendMode::Erase;
+    });
+
+    factory.get_doll_mut(0).unwrap().image = rgba(1, 1, vec![200, 100, 50, 255]);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(pixel(&image, 0, 0), [200, 100, 50, 191]);
+}
+
+#[test]
+fn fragment_blend_modes_override_those_of_slots() {
+    let (mut factory, paperdoll) =
+        single_slot_doll(1, 1, rgba(1, 1, vec![100, 200, 150, 255]), |slot| {
+            slot.blend_mode = BlendMode::Screen;
+        });
+
+    factory.get_doll_mut(0).unwrap().image = rgba(1, 1, vec![200, 100, 50, 255]);
+
+    let fragment_id = paperdoll.slot_map.values().next().copied().unwrap();
+    factory.get_fragment_mut(fragment_id).unwrap().blend_mode = Some(BlendMode::Multiply);
+
+    let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+    assert_eq!(material.slots[0].blend_mode, BlendMode::Multiply);
+
+    let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(pixel(&image, 0, 0), [78, 78, 29, 255]);
+}
+
+#[test]
+fn opacity_of_selections_overrides_that_of_slots() {
+    // No override, then overrides below and above the opacity of the slot.
+    for (opacity, alpha) in [(None, 128), (Some(0.25), 64), (Some(1.0), 255)] {
+        let (factory, paperdoll) = single_slot_doll(1, 1, rgba(1, 1, RED.to_vec()), |slot| {
+            slot.opacity = 0.5;
+        });
+
+        let (slot_id, fragment_id) = paperdoll.slot_map.iter().next().unwrap();
+
+        let builder = factory.builder().doll(0).set_slot(*slot_id, *fragment_id);
+
+        let paperdoll = match opacity {
+            Some(opacity) => builder.opacity(*slot_id, opacity),
+            None => builder,
+        }
+        .build();
+
+        let material = factory.analyse_paperdoll(&paperdoll, true).unwrap();
+
+        assert_eq!(material.slots[0].opacity, opacity.unwrap_or(0.5));
+
+        let image = factory.render_paperdoll(&paperdoll).unwrap();
+
+        assert_eq!(pixel(&image, 0, 0), [255, 0, 0, alpha], "{:?}", opacity);
+    }
+}
+
+#[test]
+fn batches_fail_only_for_invalid_paperdolls() {
+    let (factory, paperdoll) = coordinates_doll();
+
+    let invalid = Paperdoll {
+        doll: 99,
+        ..Default::default()
+    };
+
+    let paperdolls = [paperdoll.clone(), invalid, paperdoll.clone()];
+
+    let results = factory.render_batch(&paperdolls, &RenderOptions::default());
+
+    let expected = factory.render_paperdoll(&paperdoll).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+
+    for result in [&results[0], &results[2]] {
+        assert_eq!(result.as_ref().unwrap().pixels, expected.pixels);
+    }
+}
+
+#[test]
+fn batches_reuse_output_buffers() {
+    let (factory, paperdoll) = coordinates_doll();
+
+    let paperdolls = vec![paperdoll; 4];
+    let options = RenderOptions::default();
+
+    let mut images = vec![];
+
+    let results = factory.render_batch_into(&paperdolls, &options, &mut images);
+
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    let buffers = images
+        .iter()
+        .map(|image| image.pixels.as_ptr())
+        .collect::<Vec<_>>();
+
+    let results = factory.render_batch_into(&paperdolls, &options, &mut images);
+
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    for (image, buffer) in images.iter().zip(buffers) {
+        assert_eq!(image.pixels.as_ptr(), buffer);
+    }
+}
+
+#[test]
+fn renderer_redraws_only_the_changed_fragments() {
+    let mut factory = PaperdollFactory::default();
+
+    let translucent = |width, height, color: [u8; 4]| {
+        rgba(
+            width,
+            height,
+            [[color[0], color[1], color[2], 160]]
+                .repeat((width * height) as usize)
+                .concat(),
+        )
+    };
+
+    let (slot_id, red) = add_filled_slot(&mut factory, translucent(2, 2, RED), |slot| {
+        slot.positions[0] = Position::new(1.0, 1.0);
+    });
+
+    let (other_slot, blue) = add_filled_slot(&mut factory, translucent(2, 2, BLUE), |slot| {
+        slot.positions[0] = Position::new(6.0, 0.0);
+    });
+
+    // Placed 3 pixels right and 4 pixels down from the slot.
+    let green = factory.add_fragment().unwrap();
+
+    let fragment = factory.get_fragment_mut(green).unwrap();
+    fragment.pivot = Point::new(-3.0, -4.0);
+    fragment.image = translucent(3, 1, GREEN);
+
+    let pixels = (0..8 * 8)
+        .flat_map(|i| [i as u8 * 4, 255 - i as u8 * 4, 90, 200])
+        .collect();
+
+    let doll = factory.get_doll_mut(0).unwrap();
+    doll.width = 8;
+    doll.height = 8;
+    doll.image = rgba(8, 8, pixels);
+    doll.slots = vec![slot_id, other_slot];
+
+    let paperdoll = factory
+        .builder()
+        .doll(0)
+        .set_slot(slot_id, red)
+        .set_slot(other_slot, blue)
+        .build();
+
+    let mut renderer = Renderer::default();
+
+    assert_eq!(
+        renderer.render(&factory, &paperdoll).unwrap(),
+        Some(Rect::new(0, 0, 8, 8))
+    );
+
+    let mut changed = paperdoll.clone();
+    changed.slot_map.insert(slot_id, green);
+
+    // The old fragment covers (1, 1) to (3, 3), the new one (4, 5) to (7, 6).
+    assert_eq!(
+        renderer.render(&factory, &changed).unwrap(),
+        Some(Rect::new(1, 1, 6, 5))
+    );
+
+    let expected = factory.render_paperdoll(&changed).unwrap();
+
+    assert_eq!(renderer.image().pixels, expected.pixels);
+}
+
+#[test]
+fn cache_is_cleared_when_the_factory_is_modified() {
+    type Modify = fn(&mut PaperdollFactory);
+
+    // The doll, fragment and slot with id 0 are used, those with id 1 and the palette are not.
+    let modifications: [(&str, Modify); 10] = [
+        ("get_doll_mut", |factory| {
+            factory.get_doll_mut(0).unwrap();
+        }),
+        ("get_fragment_mut", |factory| {
+            factory.get_fragment_mut(0).unwrap();
+        }),
+        ("get_palette_mut", |factory| {
+            factory.get_palette_mut(0).unwrap();
+        }),
+        ("get_slot_mut", |factory| {
+            factory.get_slot_mut(0).unwrap();
+        }),
+        ("remove_doll", |factory| {
+            factory.remove_doll(1).unwrap();
+        }),
+        ("remove_fragment", |factory| {
+            factory.remove_fragment(1).unwrap();
+        }),
+        ("remove_palette", |factory| {
+            factory.remove_palette(0).unwrap();
+        }),
+        ("remove_slot", |factory| {
+            factory.remove_slot(1).unwrap();
+        }),
+        ("load_images_with", |factory| {
+            assert!(factory.load_images_with(&MemoryLoader::new()).is_empty());
+        }),
+        ("unload_images", |factory| factory.unload_images()),
+    ];
+
+    for (name, modify) in modifications {
+        let (mut factory, _) = coordinates_doll();
+
+        let (slot_id, fragment_id) =
+            add_filled_slot(&mut factory, rgba(1, 1, RED.to_vec()), |_| {});
+
+        factory.get_doll_mut(0).unwrap().slots.push(slot_id);
+
+        // Unused by the paperdoll, so that removing them keeps it valid.
+        assert_eq!(factory.add_doll().unwrap(), 1);
+        assert_eq!(factory.add_fragment().unwrap(), 1);
+        assert_eq!(factory.add_slot().unwrap(), 1);
+        assert_eq!(factory.add_palette().unwrap(), 0);
+
+        let paperdoll = factory
+            .builder()
+            .doll(0)
+            .set_slot(slot_id, fragment_id)
+            .build();
+
+        factory.set_cache_limit(1 << 20);
+
+        let first = factory.render_paperdoll(&paperdoll).unwrap();
+        let cached = factory.render_paperdoll(&paperdoll).unwrap();
+
+        assert!(Arc::ptr_eq(&first.pixels, &cached.pixels), "{}", name);
+
+        modify(&mut factory);
+
+        let rendered = factory.render_paperdoll(&paperdoll).unwrap();
+
+        assert!(!Arc::ptr_eq(&first.pixels, &rendered.pixels), "{}", name);
+        assert_eq!(first.pixels, rendered.pixels, "{}", name);
+    }
+}
+
+/// A green doll of 3x3 pixels, with a fragment overflowing to the left whose outer pixels are transparent.
+fn overflowing_doll() -> (PaperdollFactory, Paperdoll) {
+    let fragment = rgba(
+        4,
+        2,
+        [[CLEAR, RED, RED, CLEAR].concat(), [CLEAR; 4].concat()].concat(),
+    );
+
+    let (mut factory, paperdoll) = single_slot_doll(3, 3, fragment, |slot| {
+        slot.positions[0] = Position::new(-2.0, 2.0);
+    });
+
+    factory.get_doll_mut(0).unwrap().image = rgba(3, 3, [GREEN; 9].concat());
+
+    (factory, paperdoll)
+}
+
+#[test]
+fn bounds_include_overflowing_fragments() {
+    let (factory, paperdoll) = overflowing_doll();
+
+    let material = factory.analyse_paperdoll(&paperdoll, false).unwrap();
+
+    assert_eq!(material.bounds(), Rect::new(-2, 0, 5, 4));
+    assert_eq!(material.alpha_bounds(), Rect::new(-1, 0, 4, 3));
+}
+
+#[test]
+fn framing_moves_the_origin_of_the_doll() {
+    let (factory, paperdoll) = overflowing_doll();
+
+    let framed = |framing| {
+        let options = RenderOptions {
+            framing,
+            ..Default::default()
+        };
+
+        factory.render_framed(&paperdoll, &options).unwrap()
+    };
+
+    let (image, area) = framed(Framing::Doll);
+
+    assert_eq!(area, Rect::new(0, 0, 3, 3));
+    assert_eq!((image.width, image.height), (3, 3));
+    assert_eq!(pixel(&image, 0, 2), RED);
+
+    // The doll is placed at (2, 0), and the first red pixel at (1, 2).
+    let (image, area) = framed(Framing::Expand);
+
+    assert_eq!(area, Rect::new(-2, 0, 5, 4));
+    assert_eq!((image.width, image.height), (5, 4));
+    assert_eq!(pixel(&image, 1, 0), CLEAR);
+    assert_eq!(pixel(&image, 2, 0), GREEN);
+    assert_eq!(pixel(&image, 1, 2), RED);
+    assert_eq!(pixel(&image, 2, 3), CLEAR);
+
+    // The doll is placed at (1, 0), and the first red pixel at (0, 2).
+    let (image, area) = framed(Framing::Crop);
+
+    assert_eq!(area, Rect::new(-1, 0, 4, 3));
+    assert_eq!((image.width, image.height), (4, 3));
+    assert_eq!(pixel(&image, 0, 1), CLEAR);
+    assert_eq!(pixel(&image, 1, 0), GREEN);
+    assert_eq!(pixel(&image, 0, 2), RED);
+}
+
+#[test]
+fn cropping_leaves_out_transparent_margins() {
+    let (factory, paperdoll) = single_slot_doll(4, 4, rgba(1, 1, RED.to_vec()), |slot| {
+        slot.positions[0] = Position::new(2.0, 1.0);
+    });
+
+    let options = RenderOptions {
+        framing: Framing::Crop,
+        ..Default::default()
+    };
+
+    let (image, area) = factory.render_framed(&paperdoll, &options).unwrap();
+
+    assert_eq!(area, Rect::new(2, 1, 1, 1));
+    assert_eq!(image.pixels.as_slice(), RED);
+}